@@ -1,57 +1,71 @@
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 
+use crate::headless::Headless;
+use crate::level::{CurrentLevel, Level, LevelBlockKind};
+use crate::level_reloading::{CleanOnLevelReload, LevelPopulationSet};
+
 pub struct ArenaPlugin;
 
 impl Plugin for ArenaPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(setup_arena);
+        app.add_startup_system(setup_lighting);
+        app.add_system(setup_arena.in_set(LevelPopulationSet));
     }
 }
 
+/// Things rifles can land on.
 #[derive(Component)]
 pub struct Ground;
 
 fn setup_arena(
     mut commands: Commands,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
     mut mesh_assets: ResMut<Assets<Mesh>>,
-    mut material_assets: ResMut<Assets<StandardMaterial>>,
+    // Not available when running headless
+    mut material_assets: Option<ResMut<Assets<StandardMaterial>>>,
 ) {
-    const HALF_SIDE: f32 = 512.0;
-
-    let mesh = Mesh::from(shape::Box {
-        min_x: -HALF_SIDE,
-        max_x: HALF_SIDE,
-        min_y: -HALF_SIDE,
-        max_y: HALF_SIDE,
-        min_z: 0.0,
-        max_z: 60.0,
-    });
-    let collider = Collider::from_bevy_mesh(&mesh, &ComputedColliderShape::TriMesh).unwrap();
-    let mesh = mesh_assets.add(mesh);
-
-    // Ground
-    let mut cmd = commands.spawn_empty();
-    cmd.insert(PbrBundle {
-        mesh: mesh.clone(),
-        material: material_assets.add(Color::WHITE.into()),
-        transform: Transform::default().looking_at(Vec3::Y, Vec3::Z),
-        ..Default::default()
-    });
-    cmd.insert(collider.clone());
-    cmd.insert(Ground);
+    let Some(level) = levels.get(&current_level.handle) else { return };
 
-    // Walls
-    for direction in [-Vec3::Z, Vec3::X, Vec3::Z, -Vec3::X] {
+    for block in level.blocks.iter() {
+        let transform = Transform::from_translation(block.position)
+            .with_rotation(Quat::from_rotation_y(block.yaw));
         let mut cmd = commands.spawn_empty();
-        cmd.insert(PbrBundle {
-            mesh: mesh.clone(),
-            material: material_assets.add(Color::WHITE.into()),
-            transform: Transform::from_translation(-HALF_SIDE * direction + HALF_SIDE * Vec3::Y)
-                .looking_to(direction, Vec3::Y),
-            ..Default::default()
-        });
-        cmd.insert(collider.clone());
+        cmd.insert(CleanOnLevelReload);
+        if let Some(material_assets) = material_assets.as_mut() {
+            let color = match block.kind {
+                LevelBlockKind::Ground | LevelBlockKind::Wall => Color::WHITE,
+                LevelBlockKind::Obstacle => Color::GRAY,
+            };
+            cmd.insert(PbrBundle {
+                mesh: mesh_assets.add(Mesh::from(shape::Box::new(
+                    block.size.x,
+                    block.size.y,
+                    block.size.z,
+                ))),
+                material: material_assets.add(color.into()),
+                transform,
+                ..Default::default()
+            });
+        } else {
+            cmd.insert(TransformBundle::from_transform(transform));
+        }
+        let half_size = 0.5 * block.size;
+        cmd.insert(Collider::cuboid(half_size.x, half_size.y, half_size.z));
+        cmd.insert(block.kind);
+        match block.kind {
+            LevelBlockKind::Ground | LevelBlockKind::Obstacle => {
+                cmd.insert(Ground);
+            }
+            LevelBlockKind::Wall => {}
+        }
+    }
+}
+
+fn setup_lighting(mut commands: Commands, headless: Option<Res<Headless>>) {
+    if headless.is_some() {
+        return;
     }
 
     commands.spawn(PointLightBundle {
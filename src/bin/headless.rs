@@ -0,0 +1,69 @@
+//! Runs a single match without a window or a renderer and prints the outcome.
+//!
+//! Usage: `cargo run --bin headless -- [SEED] [MAX_SECONDS] [--record-replay FILE | --play-replay FILE]`
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::{NoUserData, RapierPhysicsPlugin};
+use bevy_turborand::RngPlugin;
+use round_robin_rifle::{
+    summarize_match, AppState, HeadlessGamePlugin, ReplayPlayback, ReplayRecorder,
+    HEADLESS_TIMESTEP,
+};
+
+fn main() {
+    let mut app = App::new();
+
+    let mut args = std::env::args().skip(1);
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record-replay" => {
+                let path = args.next().expect("--record-replay needs a file");
+                app.insert_resource(ReplayRecorder::new(path));
+            }
+            "--play-replay" => {
+                let path = args.next().expect("--play-replay needs a file");
+                app.insert_resource(ReplayPlayback::load(path).expect("Unable to load replay"));
+            }
+            _ => positional.push(arg),
+        }
+    }
+    let mut positional = positional.into_iter();
+    let seed: u64 = positional
+        .next()
+        .map(|arg| arg.parse().expect("SEED must be a number"))
+        .unwrap_or(0);
+    let max_seconds: f32 = positional
+        .next()
+        .map(|arg| arg.parse().expect("MAX_SECONDS must be a number"))
+        .unwrap_or(300.0);
+
+    app.add_plugins(MinimalPlugins);
+    app.add_plugin(RngPlugin::new().with_rng_seed(seed));
+    // The game adds Rapier's systems to the fixed timestep schedule itself, and Tnua's plugins
+    // along with them
+    app.add_plugin(RapierPhysicsPlugin::<NoUserData>::default().with_default_system_setup(false));
+    app.add_plugin(HeadlessGamePlugin);
+
+    let max_frames = (max_seconds / HEADLESS_TIMESTEP) as usize;
+    for frame in 0..max_frames {
+        app.update();
+        if matches!(
+            app.world.resource::<State<AppState>>().0,
+            AppState::GameOver | AppState::LevelCompleted
+        ) {
+            println!(
+                "Match over after {:.2} seconds",
+                frame as f32 * HEADLESS_TIMESTEP
+            );
+            break;
+        }
+    }
+    println!("{:#?}", summarize_match(&mut app.world));
+    if let Some(playback) = app.world.get_resource::<ReplayPlayback>() {
+        match playback.divergence() {
+            Some(divergence) => println!("Replay diverged: {:#?}", divergence),
+            None => println!("Replay matched the recording"),
+        }
+    }
+}
@@ -1,35 +1,215 @@
 use bevy::prelude::*;
+use bevy::utils::HashSet;
 use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::rifle::ShootCommand;
-use crate::{collision_groups, ShootingSequenceSet};
+use crate::headless::VisualAssets;
+use crate::killing::Killable;
+use crate::level::LevelBlockKind;
+use crate::level_reloading::CleanOnLevelReload;
+use crate::ragdoll::RagdollBone;
+use crate::rifle::{Magazine, RifleStatus, ShootCommand};
+use crate::utils::entities_ordered_by_type;
+use crate::weapon::{Weapon, WeaponDefinition};
+use crate::{collision_groups, GameplaySet, ShootingSequenceSet};
 
 pub struct BulletPlugin;
 
 impl Plugin for BulletPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(generate_bullet.in_set(ShootingSequenceSet::GenerateBullet));
+        app.add_event::<BulletImpact>();
+        app.add_systems(
+            (
+                // Hitscan rifles send their impacts right away
+                generate_bullet.in_set(ShootingSequenceSet::GenerateBullet),
+                // So that bullets generated this step get swept before their first physics step
+                apply_system_buffers,
+                sweep_bullets,
+                apply_system_buffers,
+                handle_bullet_collisions,
+                apply_system_buffers,
+                expire_bullets,
+            )
+                .chain()
+                .in_set(BulletImpactSet)
+                .in_set(GameplaySet)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
     }
 }
 
+/// How far a bullet can fly before it is despawned.
+const BULLET_MAX_RANGE: f32 = 300.0;
+/// How long a bullet can fly before it is despawned, even if it did not reach its range.
+const BULLET_MAX_LIFETIME: f32 = 5.0;
+
+/// How a rifle's shots reach their target.
+#[derive(Component, Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectileModel {
+    /// A bullet entity that flies through the level. Its path is swept with a raycast every step,
+    /// so it can't tunnel through thin walls or fast opponents.
+    #[default]
+    Projectile,
+    /// No bullet entity - the shot instantly hits whatever is first along the rifle's aim, just
+    /// like the crosshair.
+    Hitscan,
+}
+
 #[derive(Component)]
 pub struct Bullet {
     pub shooter: Entity,
+    damage: f32,
+    mass: f32,
+    origin: Vec3,
+    lifetime: Timer,
+}
+
+/// Systems that send [`BulletImpact`] events and despawn bullets.
+#[derive(SystemSet, Clone, PartialEq, Eq, Debug, Hash)]
+pub struct BulletImpactSet;
+
+/// Sent when a bullet hits something that stops it. The bullet is despawned.
+pub struct BulletImpact {
+    /// `None` for hitscan shots, which have no bullet entity.
+    pub bullet: Option<Entity>,
+    pub shooter: Entity,
+    pub target: Entity,
+    pub target_kind: BulletImpactTargetKind,
+    /// Where the bullet was when the impact was detected. For swept and hitscan shots, this is
+    /// where the ray hit the target.
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub damage: f32,
+    pub mass: f32,
+}
+
+impl BulletImpact {
+    pub fn momentum(&self) -> Vec3 {
+        self.mass * self.velocity
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BulletImpactTargetKind {
+    Level(LevelBlockKind),
+    Killable,
+    /// A rifle nobody holds, or a bone of a ragdoll. The bullet only pushes it.
+    Loose,
+}
+
+type ImpactTargetsQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Option<&'static LevelBlockKind>,
+        Option<&'static Killable>,
+        Option<&'static RifleStatus>,
+        Option<&'static RagdollBone>,
+    ),
+>;
+
+fn impact_target_kind(
+    targets_query: &ImpactTargetsQuery,
+    entity: Entity,
+) -> Option<BulletImpactTargetKind> {
+    match targets_query.get(entity).ok()? {
+        (Some(level_block_kind), _, _, _) => Some(BulletImpactTargetKind::Level(*level_block_kind)),
+        (None, Some(_), _, _) => Some(BulletImpactTargetKind::Killable),
+        // Bullets fly through the rifles participants are holding
+        (None, None, Some(RifleStatus::Equiped(_)), _) => None,
+        (None, None, Some(_), _) | (None, None, None, Some(_)) => {
+            Some(BulletImpactTargetKind::Loose)
+        }
+        // Bullets fly through other bullets
+        (None, None, None, None) => None,
+    }
+}
+
+/// Find the first thing a shot flying along the ray would hit, ignoring the shooter.
+fn cast_shot(
+    rapier_context: &RapierContext,
+    targets_query: &ImpactTargetsQuery,
+    shooter: Entity,
+    origin: Vec3,
+    direction: Vec3,
+    max_toi: f32,
+) -> Option<(Entity, BulletImpactTargetKind, Vec3)> {
+    let (target, toi) = rapier_context.cast_ray(
+        origin,
+        direction,
+        max_toi,
+        true,
+        QueryFilter::default().predicate(&|entity| {
+            entity != shooter && impact_target_kind(targets_query, entity).is_some()
+        }),
+    )?;
+    let target_kind = impact_target_kind(targets_query, target)?;
+    Some((target, target_kind, origin + toi * direction))
 }
 
+#[allow(clippy::too_many_arguments)]
 fn generate_bullet(
     mut reader: EventReader<ShootCommand>,
-    rifles_query: Query<&GlobalTransform>,
+    rifles_query: Query<(
+        &RifleStatus,
+        &GlobalTransform,
+        &Weapon,
+        Option<&ProjectileModel>,
+        Option<&Magazine>,
+    )>,
+    weapon_definitions: Res<Assets<WeaponDefinition>>,
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
+    visuals: VisualAssets,
+    rapier_context: Res<RapierContext>,
+    targets_query: ImpactTargetsQuery,
+    mut impacts_writer: EventWriter<BulletImpact>,
 ) {
-    for ShootCommand { rifle, shooter } in reader.iter() {
-        let Ok(rifle_transform) = rifles_query.get(*rifle) else { continue };
+    for ShootCommand {
+        rifle,
+        shooter,
+        aim_deviation,
+    } in reader.iter()
+    {
+        let Ok((rifle_status, rifle_transform, weapon, projectile_model, magazine)) = rifles_query.get(*rifle) else { continue };
+        if !matches!(rifle_status, RifleStatus::Equiped(holder) if holder == shooter) {
+            continue;
+        }
+        let rifle_transform =
+            rifle_transform.mul_transform(Transform::from_rotation(*aim_deviation));
+        if magazine.map_or(false, |magazine| !magazine.can_fire()) {
+            // Pulling the trigger on an empty rifle only flings it
+            continue;
+        }
+        let Some(definition) = weapon_definitions.get(&weapon.0) else { continue };
+        if let Some(ProjectileModel::Hitscan) = projectile_model {
+            let origin = rifle_transform.translation();
+            let direction = rifle_transform.forward();
+            if let Some((target, target_kind, position)) = cast_shot(
+                &rapier_context,
+                &targets_query,
+                *shooter,
+                origin,
+                direction,
+                BULLET_MAX_RANGE,
+            ) {
+                impacts_writer.send(BulletImpact {
+                    bullet: None,
+                    shooter: *shooter,
+                    target,
+                    target_kind,
+                    position,
+                    velocity: definition.bullet_speed * direction,
+                    damage: definition.bullet_damage,
+                    mass: definition.bullet_mass,
+                });
+            }
+            continue;
+        }
         let mut cmd = commands.spawn_empty();
         cmd.insert(SceneBundle {
-            scene: asset_server.load("bullet.glb#Scene0"),
+            scene: visuals.load("bullet.glb#Scene0"),
             transform: rifle_transform
-                .mul_transform(Transform::from_xyz(0.0, 0.0, -2.0))
+                .mul_transform(Transform::from_translation(definition.muzzle_offset))
                 .into(),
             ..Default::default()
         });
@@ -37,7 +217,7 @@ fn generate_bullet(
         cmd.insert(RigidBody::KinematicVelocityBased);
         cmd.insert(Collider::capsule_z(0.25, 0.25));
         cmd.insert(Velocity {
-            linvel: 100.0 * rifle_transform.forward(),
+            linvel: definition.bullet_speed * rifle_transform.forward(),
             angvel: Vec3::ZERO,
         });
         cmd.insert(ActiveEvents::COLLISION_EVENTS);
@@ -46,6 +226,123 @@ fn generate_bullet(
             filters: collision_groups::GENERAL,
         });
 
-        cmd.insert(Bullet { shooter: *shooter });
+        cmd.insert(Bullet {
+            shooter: *shooter,
+            damage: definition.bullet_damage,
+            mass: definition.bullet_mass,
+            origin: rifle_transform.translation(),
+            lifetime: Timer::from_seconds(BULLET_MAX_LIFETIME, TimerMode::Once),
+        });
+        cmd.insert(CleanOnLevelReload);
+    }
+}
+
+/// Bullets are fast enough to pass through a thin wall or an opponent within a single step without
+/// ever touching them. Look ahead along each bullet's path to catch these hits.
+fn sweep_bullets(
+    fixed_time: Res<FixedTime>,
+    // Not `GlobalTransform`, which is not propagated yet for bullets generated this step. Bullets
+    // have no parents anyway.
+    bullets_query: Query<(Entity, &Bullet, &Transform, &Velocity)>,
+    rapier_context: Res<RapierContext>,
+    targets_query: ImpactTargetsQuery,
+    mut commands: Commands,
+    mut impacts_writer: EventWriter<BulletImpact>,
+) {
+    for (
+        bullet,
+        Bullet {
+            shooter,
+            damage,
+            mass,
+            ..
+        },
+        transform,
+        velocity,
+    ) in bullets_query.iter()
+    {
+        let speed = velocity.linvel.length();
+        if speed == 0.0 {
+            continue;
+        }
+        if let Some((target, target_kind, position)) = cast_shot(
+            &rapier_context,
+            &targets_query,
+            *shooter,
+            transform.translation,
+            velocity.linvel / speed,
+            speed * fixed_time.period.as_secs_f32(),
+        ) {
+            impacts_writer.send(BulletImpact {
+                bullet: Some(bullet),
+                shooter: *shooter,
+                target,
+                target_kind,
+                position,
+                velocity: velocity.linvel,
+                damage: *damage,
+                mass: *mass,
+            });
+            commands.entity(bullet).despawn_recursive();
+        }
+    }
+}
+
+fn handle_bullet_collisions(
+    mut reader: EventReader<CollisionEvent>,
+    bullets_query: Query<(&Bullet, &GlobalTransform, &Velocity)>,
+    targets_query: ImpactTargetsQuery,
+    mut commands: Commands,
+    mut impacts_writer: EventWriter<BulletImpact>,
+) {
+    let mut despawned = HashSet::new();
+    for event in reader.iter() {
+        let CollisionEvent::Started(e1, e2, _) = event else { continue };
+        let Some([bullet, target]) = entities_ordered_by_type!([*e1, *e2], bullets_query, targets_query) else { continue };
+        let (
+            Bullet {
+                shooter,
+                damage,
+                mass,
+                ..
+            },
+            transform,
+            velocity,
+        ) = bullets_query.get(bullet).unwrap();
+        if *shooter == target || despawned.contains(&bullet) {
+            continue;
+        }
+        let Some(target_kind) = impact_target_kind(&targets_query, target) else { continue };
+        // A fresh bullet touches the rifle that fired it, which may already be flung. Loose
+        // bodies are only pushed by bullets that hit them in `sweep_bullets`.
+        if target_kind == BulletImpactTargetKind::Loose {
+            continue;
+        }
+        impacts_writer.send(BulletImpact {
+            bullet: Some(bullet),
+            shooter: *shooter,
+            target,
+            target_kind,
+            position: transform.translation(),
+            velocity: velocity.linvel,
+            damage: *damage,
+            mass: *mass,
+        });
+        commands.entity(bullet).despawn_recursive();
+        despawned.insert(bullet);
+    }
+}
+
+fn expire_bullets(
+    fixed_time: Res<FixedTime>,
+    mut bullets_query: Query<(Entity, &mut Bullet, &GlobalTransform)>,
+    mut commands: Commands,
+) {
+    for (bullet_entity, mut bullet, transform) in bullets_query.iter_mut() {
+        if bullet.lifetime.tick(fixed_time.period).finished()
+            || BULLET_MAX_RANGE < bullet.origin.distance(transform.translation())
+        {
+            commands.entity(bullet_entity).despawn_recursive();
+        }
     }
 }
@@ -1,24 +1,122 @@
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
-use bevy_tnua::TnuaPlatformerConfig;
+use bevy_tnua::{TnuaPlatformerConfig, TnuaSystemSet};
+use float_ord::FloatOrd;
 
-use crate::menu::AppState;
+use crate::utils::project_by_normal;
+use crate::{GameplaySet, ShootingSequenceSet};
 
 pub struct BumpinPlugin;
 
 impl Plugin for BumpinPlugin {
     fn build(&self, app: &mut App) {
+        app.add_event::<ShoveCommand>();
+        app.add_event::<BumpEvent>();
         app.add_systems(
-            (detect_bumpin, apply_bumpin)
+            (
+                detect_bumpin,
+                handle_shoving.after(ShootingSequenceSet::ShootInitiator),
+                apply_bumpin,
+            )
                 .chain()
-                .in_set(OnUpdate(AppState::Game)),
+                .in_set(BumpSet)
+                .in_set(GameplaySet)
+                .in_schedule(CoreSchedule::FixedUpdate),
         );
+        app.add_system({
+            record_pre_step_velocity
+                .after(TnuaSystemSet::Motors)
+                .before(PhysicsSet::SyncBackend)
+                .in_schedule(CoreSchedule::FixedUpdate)
+        });
     }
 }
 
+/// Systems that detect bumps, send [`BumpEvent`]s and apply them.
+#[derive(SystemSet, Clone, PartialEq, Eq, Debug, Hash)]
+pub struct BumpSet;
+
 #[derive(Component)]
 pub struct BumpInitiator;
 
+/// How an entity reacts to being bumped. Entities without it use the defaults.
+#[derive(Component, Debug, Clone)]
+pub struct BumpConfig {
+    /// Collisions where the bodies approach each other slower than this do not bump.
+    pub min_speed: f32,
+    /// The impulse of a collision bump, per unit of approach speed.
+    pub impulse_per_speed: f32,
+    pub max_impulse: f32,
+    /// The acceleration (and air acceleration) drops to this right after the bump, and recovers
+    /// gradually while the entity is losing its footing.
+    pub lowered_acceleration: f32,
+    /// Seconds of lost footing per unit of bump impulse.
+    pub recovery_per_impulse: f32,
+    pub max_recovery: f32,
+    /// Bumps at least this strong knock the rifle out of the entity's hands.
+    pub disarm_impulse: f32,
+}
+
+impl Default for BumpConfig {
+    fn default() -> Self {
+        Self {
+            min_speed: 5.0,
+            impulse_per_speed: 1.0,
+            max_impulse: 40.0,
+            lowered_acceleration: -60.0,
+            recovery_per_impulse: 0.025,
+            max_recovery: 1.5,
+            disarm_impulse: 25.0,
+        }
+    }
+}
+
+impl BumpConfig {
+    fn collision_impulse(&self, approach_speed: f32) -> Option<f32> {
+        if approach_speed < self.min_speed {
+            None
+        } else {
+            Some((self.impulse_per_speed * approach_speed).min(self.max_impulse))
+        }
+    }
+
+    fn recovery_duration(&self, impulse: Vec3) -> f32 {
+        (self.recovery_per_impulse * impulse.length()).min(self.max_recovery)
+    }
+}
+
+/// Sent when an entity gets bumped - by colliding with someone, by being shoved, or by being hit
+/// with a thrown rifle.
+#[derive(Debug)]
+pub struct BumpEvent {
+    pub initiator: Entity,
+    pub victim: Entity,
+    pub impulse: Vec3,
+}
+
+/// Can deliberately bump whoever is right in front of it.
+#[derive(Component, Default)]
+pub struct Shover {
+    cooldown: Option<Timer>,
+}
+
+pub struct ShoveCommand {
+    pub shover: Entity,
+}
+
+/// How far in front of the shover a shove reaches.
+const SHOVE_RANGE: f32 = 4.0;
+/// How far (in radians) to each side of the shover's forward direction a shove reaches.
+const SHOVE_CONE_HALF_ANGLE: f32 = 0.6;
+const SHOVE_IMPULSE: f32 = 40.0;
+const SHOVE_COOLDOWN: f32 = 1.5;
+
+/// The entity's velocity right before the last physics step. By the time a collision is reported
+/// the solver has already resolved it, so the `Velocity` no longer shows how fast the bodies were
+/// approaching each other.
+#[derive(Component, Default, Debug)]
+pub struct PreStepVelocity(pub Vec3);
+
 #[derive(Component, Default, Debug)]
 pub enum BumpStatus {
     #[default]
@@ -58,19 +156,37 @@ impl AccelerationRestoration {
 }
 
 impl BumpStatus {
-    fn trigger_bump_if_empty(&mut self, impulse: Vec3) {
+    /// Returns `true` if the bump was triggered.
+    pub fn trigger_bump_if_empty(&mut self, impulse: Vec3) -> bool {
         if matches!(self, Self::NoBump) {
             *self = Self::TriggerBump { impulse };
+            true
+        } else {
+            false
         }
     }
 }
 
+fn record_pre_step_velocity(mut query: Query<(&mut PreStepVelocity, &Velocity)>) {
+    for (mut pre_step_velocity, velocity) in query.iter_mut() {
+        pre_step_velocity.0 = velocity.linvel;
+    }
+}
+
+/// How fast the bodies were approaching each other along `normal`, which points from the other
+/// entity to the initiator.
+fn approach_speed(initiator_velocity: Vec3, other_velocity: Vec3, normal: Vec3) -> f32 {
+    (other_velocity - initiator_velocity).dot(normal)
+}
+
 fn detect_bumpin(
     mut reader: EventReader<CollisionEvent>,
     initiator_query: Query<&BumpInitiator>,
-    mut status_query: Query<&mut BumpStatus>,
+    mut status_query: Query<(&mut BumpStatus, &PreStepVelocity, Option<&BumpConfig>)>,
     rapier: Res<RapierContext>,
+    mut bump_events_writer: EventWriter<BumpEvent>,
 ) {
+    let default_config = BumpConfig::default();
     for event in reader.iter() {
         let CollisionEvent::Started(e1, e2, _flags) = event else { continue };
         // ie stands for "initiator entity", and oe for "other entity". These prefixes will also be
@@ -82,7 +198,7 @@ fn detect_bumpin(
         } else {
             continue;
         };
-        let Ok([mut istatus, mut ostatus]) = status_query.get_many_mut([ie, oe]) else { continue };
+        let Ok([(mut istatus, ivelocity, iconfig), (mut ostatus, ovelocity, oconfig)]) = status_query.get_many_mut([ie, oe]) else { continue };
 
         let Some(contact_pair) = rapier.contact_pair(ie, oe) else { continue };
         let normal = contact_pair
@@ -96,30 +212,101 @@ fn detect_bumpin(
             normal
         };
 
-        let normal = normal * 20.0;
-        istatus.trigger_bump_if_empty(normal);
-        ostatus.trigger_bump_if_empty(-normal);
+        let approach_speed = approach_speed(ivelocity.0, ovelocity.0, normal);
+        for (status, config, victim, initiator, direction) in [
+            (&mut istatus, iconfig, ie, oe, normal),
+            (&mut ostatus, oconfig, oe, ie, -normal),
+        ] {
+            let config = config.unwrap_or(&default_config);
+            let Some(impulse) = config.collision_impulse(approach_speed) else { continue };
+            let impulse = impulse * direction;
+            if status.trigger_bump_if_empty(impulse) {
+                bump_events_writer.send(BumpEvent {
+                    initiator,
+                    victim,
+                    impulse,
+                });
+            }
+        }
+    }
+}
+
+fn handle_shoving(
+    fixed_time: Res<FixedTime>,
+    mut reader: EventReader<ShoveCommand>,
+    mut shovers_query: Query<(&mut Shover, &GlobalTransform)>,
+    mut status_query: Query<(Entity, &mut BumpStatus, &GlobalTransform)>,
+    mut bump_events_writer: EventWriter<BumpEvent>,
+) {
+    for (mut shover, _) in shovers_query.iter_mut() {
+        if let Some(cooldown) = shover.cooldown.as_mut() {
+            if cooldown.tick(fixed_time.period).finished() {
+                shover.cooldown = None;
+            }
+        }
+    }
+    for ShoveCommand { shover } in reader.iter() {
+        let Ok((mut shover_status, shover_transform)) = shovers_query.get_mut(*shover) else { continue };
+        if shover_status.cooldown.is_some() {
+            continue;
+        }
+        let shover_position = shover_transform.translation();
+        let shover_forward = project_by_normal(shover_transform.forward(), Vec3::Y);
+        let victim = status_query
+            .iter_mut()
+            .filter_map(|(entity, status, transform)| {
+                if entity == *shover {
+                    return None;
+                }
+                let vector_to_victim =
+                    project_by_normal(transform.translation() - shover_position, Vec3::Y);
+                if SHOVE_RANGE < vector_to_victim.length()
+                    || SHOVE_CONE_HALF_ANGLE < shover_forward.angle_between(vector_to_victim)
+                {
+                    return None;
+                }
+                Some((entity, status, vector_to_victim))
+            })
+            .min_by_key(|(_, _, vector_to_victim)| FloatOrd(vector_to_victim.length_squared()));
+        // Shoving thin air still uses up the shove
+        shover_status.cooldown = Some(Timer::from_seconds(SHOVE_COOLDOWN, TimerMode::Once));
+        let Some((victim, mut victim_status, vector_to_victim)) = victim else { continue };
+        let impulse = SHOVE_IMPULSE * vector_to_victim.normalize_or_zero();
+        if victim_status.trigger_bump_if_empty(impulse) {
+            bump_events_writer.send(BumpEvent {
+                initiator: *shover,
+                victim,
+                impulse,
+            });
+        }
     }
 }
 
 fn apply_bumpin(
-    mut query: Query<(&mut BumpStatus, &mut Velocity, &mut TnuaPlatformerConfig)>,
-    time: Res<Time>,
+    mut query: Query<(
+        &mut BumpStatus,
+        &mut Velocity,
+        &mut TnuaPlatformerConfig,
+        Option<&BumpConfig>,
+    )>,
+    fixed_time: Res<FixedTime>,
 ) {
-    for (mut status, mut velocity, mut tnua_config) in query.iter_mut() {
+    let default_config = BumpConfig::default();
+    for (mut status, mut velocity, mut tnua_config, config) in query.iter_mut() {
+        let config = config.unwrap_or(&default_config);
         match status.as_mut() {
             BumpStatus::NoBump => {}
             BumpStatus::TriggerBump { impulse } => {
                 velocity.linvel += *impulse;
                 *status = BumpStatus::LostFooting {
-                    timer: Timer::from_seconds(0.5, TimerMode::Once),
+                    timer: Timer::from_seconds(config.recovery_duration(*impulse), TimerMode::Once),
                     acceleration_restoration: AccelerationRestoration::get_and_update(
                         &mut tnua_config.acceleration,
-                        -60.0,
+                        config.lowered_acceleration,
                     ),
                     air_acceleration_restoration: AccelerationRestoration::get_and_update(
                         &mut tnua_config.air_acceleration,
-                        -60.0,
+                        config.lowered_acceleration,
                     ),
                 }
             }
@@ -128,7 +315,7 @@ fn apply_bumpin(
                 acceleration_restoration,
                 air_acceleration_restoration,
             } => {
-                timer.tick(time.delta());
+                timer.tick(fixed_time.period);
                 let percent = timer.percent();
                 if timer.finished() {
                     tnua_config.acceleration = acceleration_restoration.original;
@@ -142,3 +329,28 @@ fn apply_bumpin(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn head_on_run_at_full_speed_bumps() {
+        let config = BumpConfig::default();
+        // The player runs at 40 into an opponent standing still, colliding head-on along X
+        let normal = -Vec3::X;
+        let speed = approach_speed(40.0 * Vec3::X, Vec3::ZERO, normal);
+        assert_eq!(config.collision_impulse(speed), Some(config.max_impulse));
+        // Same with an opponent running at 20 into the player
+        let speed = approach_speed(Vec3::ZERO, -20.0 * Vec3::X, normal);
+        assert_eq!(config.collision_impulse(speed), Some(20.0));
+    }
+
+    #[test]
+    fn resolved_contact_does_not_bump() {
+        let config = BumpConfig::default();
+        // After the solver, both bodies move together and there is no approach speed left
+        let speed = approach_speed(5.0 * Vec3::X, 5.0 * Vec3::X, -Vec3::X);
+        assert_eq!(config.collision_impulse(speed), None);
+    }
+}
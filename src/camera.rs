@@ -1,5 +1,7 @@
 use bevy::prelude::*;
+use bevy::transform::TransformSystem;
 
+use crate::interpolation::PhysicsInterpolationSet;
 use crate::rifle::AimElevation;
 
 pub struct GameCameraPlugin;
@@ -7,7 +9,13 @@ pub struct GameCameraPlugin;
 impl Plugin for GameCameraPlugin {
     fn build(&self, app: &mut App) {
         app.add_startup_system(setup_camera);
-        app.add_system(update_camera);
+        // Follow the interpolated pose, not the one from the last fixed step
+        app.add_system({
+            update_camera
+                .in_base_set(CoreSet::PostUpdate)
+                .after(PhysicsInterpolationSet)
+                .before(TransformSystem::TransformPropagate)
+        });
     }
 }
 
@@ -25,11 +33,11 @@ fn setup_camera(mut commands: Commands) {
 
 fn update_camera(
     mut cameras_query: Query<&mut Transform, With<Camera3d>>,
-    camera_follow_query: Query<(&CameraFollow, &GlobalTransform, &AimElevation)>,
+    camera_follow_query: Query<(&CameraFollow, &Transform, &AimElevation), Without<Camera3d>>,
 ) {
     let Ok((camera_follow, camera_follow_transform, AimElevation(aim_elevation))) = camera_follow_query.get_single() else { return };
     let sideways = camera_follow.direction.cross(Vec3::Y).normalize_or_zero();
-    let object_at = camera_follow_transform.translation();
+    let object_at = camera_follow_transform.translation;
     let camera_at = object_at - 10.0 * camera_follow.direction + 1.0 * Vec3::Y;
     let mut target_transform =
         Transform::from_translation(camera_at).looking_at(object_at, Vec3::Y);
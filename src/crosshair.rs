@@ -1,14 +1,22 @@
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 
+use crate::headless::VisualAssets;
 use crate::rifle::RifleStatus;
+use crate::{GameplaySet, PostPhysicsPropagateSet};
 
 pub struct CrosshairPlugin;
 
 impl Plugin for CrosshairPlugin {
     fn build(&self, app: &mut App) {
         app.add_system(create_crossair);
-        app.add_system(update_crosshairs);
+        // Opponents decide what to do based on who aims at them
+        app.add_system({
+            update_crosshairs
+                .after(PostPhysicsPropagateSet)
+                .before(GameplaySet)
+                .in_schedule(CoreSchedule::FixedUpdate)
+        });
     }
 }
 
@@ -28,13 +36,13 @@ pub struct Intimidatable;
 fn create_crossair(
     rifles_query: Query<Entity, Added<RifleStatus>>,
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
+    visuals: VisualAssets,
 ) {
     for rifle in rifles_query.iter() {
         let mut cmd = commands.spawn_empty();
         cmd.insert(Crosshair { owner: rifle });
         cmd.insert(SceneBundle {
-            scene: asset_server.load("crosshair.glb#Scene0"),
+            scene: visuals.load("crosshair.glb#Scene0"),
             visibility: Visibility::Hidden,
             ..Default::default()
         });
@@ -63,6 +71,7 @@ fn update_crosshairs(
                     f32::INFINITY,
                     false,
                     QueryFilter::default()
+                        .exclude_sensors()
                         .predicate(&|entity| entity != crosshair.owner && entity != *holder),
                 ) {
                     *visibility = Visibility::Inherited;
@@ -0,0 +1,107 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub struct DifficultyPlugin;
+
+impl Plugin for DifficultyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SelectedDifficulty>();
+    }
+}
+
+/// The difficulty chosen in the menu. Applies to opponents whose spawn does not specify one.
+#[derive(Resource, Default, Debug, Clone)]
+pub struct SelectedDifficulty(pub Difficulty);
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+    Custom(DifficultySettings),
+}
+
+impl Difficulty {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+            Difficulty::Custom(_) => "Custom",
+        }
+    }
+
+    pub fn settings(&self) -> DifficultySettings {
+        match self {
+            Difficulty::Easy => DifficultySettings {
+                reaction_delay: 1.5,
+                follow_up_delay: 0.6,
+                aim_error: 0.1,
+                speed_factor: 0.8,
+                target_preference: TargetPreference::Facing,
+            },
+            Difficulty::Normal => DifficultySettings::default(),
+            Difficulty::Hard => DifficultySettings {
+                reaction_delay: 0.6,
+                follow_up_delay: 0.2,
+                aim_error: 0.0,
+                speed_factor: 1.2,
+                target_preference: TargetPreference::Player,
+            },
+            Difficulty::Custom(settings) => settings.clone(),
+        }
+    }
+
+    /// The next preset in the menu. Custom settings can only come from the level or the editor,
+    /// so the menu does not cycle through them.
+    pub fn next(&self) -> Self {
+        match self {
+            Difficulty::Easy => Difficulty::Normal,
+            Difficulty::Normal => Difficulty::Hard,
+            Difficulty::Hard | Difficulty::Custom(_) => Difficulty::Easy,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct DifficultySettings {
+    /// How long an opponent hesitates before taking its first shot, and before switching to what
+    /// it decided to do next.
+    pub reaction_delay: f32,
+    /// How long an opponent hesitates between shots from the same magazine.
+    pub follow_up_delay: f32,
+    /// Shots deviate randomly up to this angle (in radians) from where the rifle is aimed.
+    pub aim_error: f32,
+    /// Multiplies the opponent's speed and turning rate.
+    pub speed_factor: f32,
+    pub target_preference: TargetPreference,
+}
+
+impl Default for DifficultySettings {
+    fn default() -> Self {
+        Self {
+            reaction_delay: 1.0,
+            follow_up_delay: 0.3,
+            aim_error: 0.0,
+            speed_factor: 1.0,
+            target_preference: TargetPreference::Facing,
+        }
+    }
+}
+
+/// Which participant an opponent turns to when looking for someone to shoot.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetPreference {
+    /// Whoever requires the least turning.
+    #[default]
+    Facing,
+    Closest,
+    /// The player when possible, otherwise whoever requires the least turning.
+    Player,
+}
+
+/// The difficulty settings an opponent was spawned with.
+#[derive(Component, Debug, Clone)]
+pub struct OpponentDifficulty(pub DifficultySettings);
@@ -0,0 +1,544 @@
+use bevy::input::mouse::MouseMotion;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::bullet::ProjectileModel;
+use crate::difficulty::{Difficulty, TargetPreference};
+use crate::level::{Level, LevelBlock, LevelBlockKind, LevelSelection, OpponentSpawn, RifleSpawn};
+use crate::level_reloading::CleanOnLevelReload;
+use crate::menu::AppState;
+use crate::weapon::DEFAULT_WEAPON;
+
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EditorState>();
+        app.add_system(enter_editor.in_schedule(OnEnter(AppState::Editor)));
+        app.add_system(fly_camera.in_set(OnUpdate(AppState::Editor)));
+        app.add_system(editor_panel.in_set(OnUpdate(AppState::Editor)));
+        app.add_system({
+            sync_previews
+                .in_set(OnUpdate(AppState::Editor))
+                .after(editor_panel)
+        });
+    }
+}
+
+#[derive(Resource, Default)]
+struct EditorState {
+    /// The working copy. `None` until the level being edited is loaded.
+    level: Option<Level>,
+    /// The level the working copy was taken from.
+    source: Handle<Level>,
+    selected: Option<EditorSelection>,
+    file_name: String,
+    status: String,
+    previews_dirty: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum EditorSelection {
+    Block(usize),
+    Player,
+    Opponent(usize),
+    Rifle(usize),
+}
+
+/// Visual stand-ins for the level's content while editing.
+#[derive(Component)]
+struct EditorPreview;
+
+fn enter_editor(mut editor: ResMut<EditorState>, level_selection: LevelSelection) {
+    let current_level = level_selection.current();
+    if editor.source != current_level.handle {
+        editor.level = None;
+        editor.source = current_level.handle.clone();
+        editor.selected = None;
+        editor.status.clear();
+        editor.file_name = current_level
+            .index_entry
+            .and_then(|entry| level_selection.index()?.levels.get(entry))
+            .map(|entry| entry.file.clone())
+            .unwrap_or_else(|| "new.level.ron".to_owned());
+    }
+    editor.previews_dirty = true;
+}
+
+fn fly_camera(
+    time: Res<Time>,
+    mut egui_context: EguiContexts,
+    keyboard: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    mut mouse_motion: EventReader<MouseMotion>,
+    mut cameras_query: Query<&mut Transform, With<Camera3d>>,
+) {
+    let mouse_delta: Vec2 = mouse_motion.iter().map(|motion| motion.delta).sum();
+    let Ok(mut camera_transform) = cameras_query.get_single_mut() else { return };
+
+    if mouse_buttons.pressed(MouseButton::Right) {
+        camera_transform.rotate_y(-0.003 * mouse_delta.x);
+        camera_transform.rotate_local_x(-0.003 * mouse_delta.y);
+    }
+
+    if egui_context.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    let mut direction = Vec3::ZERO;
+    for (key, key_direction) in [
+        (KeyCode::W, camera_transform.forward()),
+        (KeyCode::S, camera_transform.back()),
+        (KeyCode::A, camera_transform.left()),
+        (KeyCode::D, camera_transform.right()),
+        (KeyCode::E, Vec3::Y),
+        (KeyCode::Q, -Vec3::Y),
+    ] {
+        if keyboard.pressed(key) {
+            direction += key_direction;
+        }
+    }
+    let speed = if keyboard.pressed(KeyCode::LShift) {
+        120.0
+    } else {
+        30.0
+    };
+    camera_transform.translation += speed * time.delta_seconds() * direction.normalize_or_zero();
+}
+
+fn editor_panel(
+    mut egui_context: EguiContexts,
+    mut editor: ResMut<EditorState>,
+    mut level_selection: LevelSelection,
+    mut levels: ResMut<Assets<Level>>,
+    mut state: ResMut<NextState<AppState>>,
+    cameras_query: Query<&GlobalTransform, With<Camera3d>>,
+) {
+    let editor = &mut *editor;
+    if editor.level.is_none() {
+        editor.level = levels.get(&editor.source).cloned();
+        editor.previews_dirty = true;
+    }
+    // New things are placed in front of the camera
+    let placement = cameras_query
+        .get_single()
+        .map(|camera| {
+            let mut position = camera.translation() + 20.0 * camera.forward();
+            position.y = position.y.max(2.0);
+            position
+        })
+        .unwrap_or(Vec3::new(0.0, 2.0, 0.0));
+
+    egui::SidePanel::left("editor-panel").show(egui_context.ctx_mut(), |ui| {
+        ui.heading("Level Editor");
+        ui.label("Right drag to look, WASD/QE to fly, Shift to fly faster");
+
+        let Some(level) = editor.level.as_mut() else {
+            ui.label("Loading...");
+            if ui.button("Exit Editor").clicked() {
+                state.set(AppState::MainMenu);
+            }
+            return;
+        };
+
+        ui.horizontal(|ui| {
+            ui.label("File:");
+            ui.text_edit_singleline(&mut editor.file_name);
+        });
+        ui.horizontal(|ui| {
+            #[cfg(not(target_arch = "wasm32"))]
+            if ui.button("Save").clicked() {
+                editor.status = match save_level(level, &editor.file_name, &mut level_selection) {
+                    Ok(()) => format!("Saved {}", editor.file_name),
+                    Err(err) => format!("Failed to save: {}", err),
+                };
+            }
+            if ui.button("Playtest").clicked() {
+                let handle = levels.add(level.clone());
+                level_selection.replace_current(handle.clone());
+                // So that coming back from the playtest keeps editing the same working copy
+                editor.source = handle;
+                state.set(AppState::LoadLevel);
+            }
+            if ui.button("Exit Editor").clicked() {
+                state.set(AppState::MainMenu);
+            }
+        });
+        if !editor.status.is_empty() {
+            ui.label(editor.status.as_str());
+        }
+        ui.separator();
+
+        ui.horizontal_wrapped(|ui| {
+            for kind in [
+                LevelBlockKind::Ground,
+                LevelBlockKind::Wall,
+                LevelBlockKind::Obstacle,
+            ] {
+                if ui.button(format!("Add {:?}", kind)).clicked() {
+                    level.blocks.push(LevelBlock {
+                        kind,
+                        position: placement,
+                        size: Vec3::new(4.0, 8.0, 4.0),
+                        yaw: 0.0,
+                    });
+                    editor.selected = Some(EditorSelection::Block(level.blocks.len() - 1));
+                    editor.previews_dirty = true;
+                }
+            }
+            if ui.button("Add Opponent").clicked() {
+                level.opponents.push(OpponentSpawn {
+                    position: placement,
+                    config: Default::default(),
+                    difficulty: None,
+                });
+                editor.selected = Some(EditorSelection::Opponent(level.opponents.len() - 1));
+                editor.previews_dirty = true;
+            }
+            if ui.button("Add Rifle").clicked() {
+                level.rifles.push(RifleSpawn {
+                    position: placement,
+                    scatter: 0.0,
+                    projectile: Default::default(),
+                    weapon: DEFAULT_WEAPON.to_owned(),
+                });
+                editor.selected = Some(EditorSelection::Rifle(level.rifles.len() - 1));
+                editor.previews_dirty = true;
+            }
+        });
+        ui.separator();
+
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .show(ui, |ui| {
+                let mut selectable =
+                    |ui: &mut egui::Ui, selection: EditorSelection, text: String| {
+                        if ui
+                            .selectable_label(editor.selected == Some(selection), text)
+                            .clicked()
+                        {
+                            editor.selected = Some(selection);
+                            editor.previews_dirty = true;
+                        }
+                    };
+                selectable(ui, EditorSelection::Player, "Player".to_owned());
+                for (idx, block) in level.blocks.iter().enumerate() {
+                    selectable(
+                        ui,
+                        EditorSelection::Block(idx),
+                        format!("{:?} #{}", block.kind, idx),
+                    );
+                }
+                for idx in 0..level.opponents.len() {
+                    selectable(
+                        ui,
+                        EditorSelection::Opponent(idx),
+                        format!("Opponent #{}", idx),
+                    );
+                }
+                for idx in 0..level.rifles.len() {
+                    selectable(ui, EditorSelection::Rifle(idx), format!("Rifle #{}", idx));
+                }
+            });
+        ui.separator();
+
+        let Some(selected) = editor.selected else { return };
+        let mut changed = false;
+        let mut delete = false;
+        match selected {
+            EditorSelection::Block(idx) => {
+                let Some(block) = level.blocks.get_mut(idx) else { return };
+                egui::ComboBox::from_label("Kind")
+                    .selected_text(format!("{:?}", block.kind))
+                    .show_ui(ui, |ui| {
+                        for kind in [
+                            LevelBlockKind::Ground,
+                            LevelBlockKind::Wall,
+                            LevelBlockKind::Obstacle,
+                        ] {
+                            changed |= ui
+                                .selectable_value(&mut block.kind, kind, format!("{:?}", kind))
+                                .changed();
+                        }
+                    });
+                changed |= vec3_editor(ui, "Position", &mut block.position);
+                changed |= vec3_editor(ui, "Size", &mut block.size);
+                ui.horizontal(|ui| {
+                    ui.label("Yaw");
+                    changed |= ui.drag_angle(&mut block.yaw).changed();
+                });
+                delete = ui.button("Delete").clicked();
+                if delete {
+                    level.blocks.remove(idx);
+                }
+            }
+            EditorSelection::Player => {
+                changed |= vec3_editor(ui, "Position", &mut level.player.position);
+                changed |= vec3_editor(ui, "Facing", &mut level.player.facing);
+            }
+            EditorSelection::Opponent(idx) => {
+                let Some(opponent) = level.opponents.get_mut(idx) else { return };
+                changed |= vec3_editor(ui, "Position", &mut opponent.position);
+                for (label, value) in [
+                    ("Speed", &mut opponent.config.full_speed),
+                    ("Jump Height", &mut opponent.config.full_jump_height),
+                    ("Turning", &mut opponent.config.turning_angvel),
+                ] {
+                    ui.horizontal(|ui| {
+                        ui.label(label);
+                        changed |= ui
+                            .add(
+                                egui::DragValue::new(value)
+                                    .speed(0.1)
+                                    .clamp_range(0.0..=100.0),
+                            )
+                            .changed();
+                    });
+                }
+                // Opponents without a difficulty use the one selected in the menu
+                let difficulty_name = opponent
+                    .difficulty
+                    .as_ref()
+                    .map_or("Menu", |difficulty| difficulty.name());
+                egui::ComboBox::from_label("Difficulty")
+                    .selected_text(difficulty_name)
+                    .show_ui(ui, |ui| {
+                        for option in [
+                            None,
+                            Some(Difficulty::Easy),
+                            Some(Difficulty::Normal),
+                            Some(Difficulty::Hard),
+                            Some(Difficulty::Custom(Default::default())),
+                        ] {
+                            let name = option
+                                .as_ref()
+                                .map_or("Menu", |difficulty| difficulty.name());
+                            if ui.selectable_label(name == difficulty_name, name).clicked()
+                                && name != difficulty_name
+                            {
+                                opponent.difficulty = option;
+                                changed = true;
+                            }
+                        }
+                    });
+                if let Some(Difficulty::Custom(settings)) = opponent.difficulty.as_mut() {
+                    for (label, value) in [
+                        ("Reaction Delay", &mut settings.reaction_delay),
+                        ("Follow-up Delay", &mut settings.follow_up_delay),
+                        ("Aim Error", &mut settings.aim_error),
+                        ("Speed Factor", &mut settings.speed_factor),
+                    ] {
+                        ui.horizontal(|ui| {
+                            ui.label(label);
+                            changed |= ui
+                                .add(
+                                    egui::DragValue::new(value)
+                                        .speed(0.01)
+                                        .clamp_range(0.0..=10.0),
+                                )
+                                .changed();
+                        });
+                    }
+                    egui::ComboBox::from_label("Target Preference")
+                        .selected_text(format!("{:?}", settings.target_preference))
+                        .show_ui(ui, |ui| {
+                            for preference in [
+                                TargetPreference::Facing,
+                                TargetPreference::Closest,
+                                TargetPreference::Player,
+                            ] {
+                                changed |= ui
+                                    .selectable_value(
+                                        &mut settings.target_preference,
+                                        preference,
+                                        format!("{:?}", preference),
+                                    )
+                                    .changed();
+                            }
+                        });
+                }
+                delete = ui.button("Delete").clicked();
+                if delete {
+                    level.opponents.remove(idx);
+                }
+            }
+            EditorSelection::Rifle(idx) => {
+                let Some(rifle) = level.rifles.get_mut(idx) else { return };
+                changed |= vec3_editor(ui, "Position", &mut rifle.position);
+                ui.horizontal(|ui| {
+                    ui.label("Scatter");
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut rifle.scatter)
+                                .speed(0.1)
+                                .clamp_range(0.0..=100.0),
+                        )
+                        .changed();
+                });
+                egui::ComboBox::from_label("Projectile")
+                    .selected_text(format!("{:?}", rifle.projectile))
+                    .show_ui(ui, |ui| {
+                        for projectile in [ProjectileModel::Projectile, ProjectileModel::Hitscan] {
+                            changed |= ui
+                                .selectable_value(
+                                    &mut rifle.projectile,
+                                    projectile,
+                                    format!("{:?}", projectile),
+                                )
+                                .changed();
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    ui.label("Weapon");
+                    changed |= ui.text_edit_singleline(&mut rifle.weapon).changed();
+                });
+                delete = ui.button("Delete").clicked();
+                if delete {
+                    level.rifles.remove(idx);
+                }
+            }
+        }
+        if delete {
+            editor.selected = None;
+        }
+        if changed || delete {
+            editor.previews_dirty = true;
+        }
+    });
+}
+
+fn vec3_editor(ui: &mut egui::Ui, label: &str, value: &mut Vec3) -> bool {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        let mut changed = false;
+        for (prefix, coord) in [
+            ("x: ", &mut value.x),
+            ("y: ", &mut value.y),
+            ("z: ", &mut value.z),
+        ] {
+            changed |= ui
+                .add(egui::DragValue::new(coord).speed(0.1).prefix(prefix))
+                .changed();
+        }
+        changed
+    })
+    .inner
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn save_level(
+    level: &Level,
+    file_name: &str,
+    level_selection: &mut LevelSelection,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use crate::level::LevelIndexEntry;
+
+    let Some(name) = file_name.strip_suffix(".level.ron") else {
+        return Err("level files must end with .level.ron".into());
+    };
+    let levels_dir = bevy::asset::FileAssetIo::get_base_path()
+        .join("assets")
+        .join("levels");
+    let pretty_config = ron::ser::PrettyConfig::default();
+    std::fs::write(
+        levels_dir.join(file_name),
+        ron::ser::to_string_pretty(level, pretty_config.clone())?,
+    )?;
+
+    // Make new levels available in the level selection menu. Without the index loaded, writing it
+    // would drop every other level from the menu.
+    if level_selection
+        .register(LevelIndexEntry {
+            name: name.to_owned(),
+            file: file_name.to_owned(),
+        })
+        .is_none()
+    {
+        return Err("the level was saved, but the level index is not loaded to add it to".into());
+    }
+    if let Some(index) = level_selection.index() {
+        std::fs::write(
+            levels_dir.join("levels.index.ron"),
+            ron::ser::to_string_pretty(index, pretty_config)?,
+        )?;
+    }
+    Ok(())
+}
+
+fn sync_previews(
+    mut commands: Commands,
+    mut editor: ResMut<EditorState>,
+    previews_query: Query<Entity, With<EditorPreview>>,
+    mut mesh_assets: ResMut<Assets<Mesh>>,
+    mut material_assets: ResMut<Assets<StandardMaterial>>,
+) {
+    if !editor.previews_dirty {
+        return;
+    }
+    let Some(level) = editor.level.as_ref() else { return };
+    for entity in previews_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let mut spawn_preview =
+        |selection: EditorSelection, mesh: Mesh, color: Color, transform: Transform| {
+            let color = if editor.selected == Some(selection) {
+                Color::ORANGE
+            } else {
+                color
+            };
+            let mut cmd = commands.spawn_empty();
+            cmd.insert(PbrBundle {
+                mesh: mesh_assets.add(mesh),
+                material: material_assets.add(color.into()),
+                transform,
+                ..Default::default()
+            });
+            cmd.insert(EditorPreview);
+            cmd.insert(CleanOnLevelReload);
+        };
+
+    for (idx, block) in level.blocks.iter().enumerate() {
+        let color = match block.kind {
+            LevelBlockKind::Ground | LevelBlockKind::Wall => Color::WHITE,
+            LevelBlockKind::Obstacle => Color::GRAY,
+        };
+        spawn_preview(
+            EditorSelection::Block(idx),
+            Mesh::from(shape::Box::new(block.size.x, block.size.y, block.size.z)),
+            color,
+            Transform::from_translation(block.position)
+                .with_rotation(Quat::from_rotation_y(block.yaw)),
+        );
+    }
+    let participant_mesh = || {
+        Mesh::from(shape::Capsule {
+            radius: 0.5,
+            depth: 1.0,
+            ..Default::default()
+        })
+    };
+    spawn_preview(
+        EditorSelection::Player,
+        participant_mesh(),
+        Color::GREEN,
+        Transform::from_translation(level.player.position).looking_to(level.player.facing, Vec3::Y),
+    );
+    for (idx, opponent) in level.opponents.iter().enumerate() {
+        spawn_preview(
+            EditorSelection::Opponent(idx),
+            participant_mesh(),
+            Color::RED,
+            Transform::from_translation(opponent.position),
+        );
+    }
+    for (idx, rifle) in level.rifles.iter().enumerate() {
+        spawn_preview(
+            EditorSelection::Rifle(idx),
+            Mesh::from(shape::Box::new(0.2, 0.2, 2.0)),
+            Color::YELLOW,
+            Transform::from_translation(rifle.position),
+        );
+    }
+
+    editor.previews_dirty = false;
+}
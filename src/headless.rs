@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use bevy::asset::Asset;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+
+use crate::killing::Killable;
+use crate::level_outcome::LevelOutcome;
+use crate::menu::AppState;
+use crate::player::IsPlayer;
+use crate::score::ScoreHaver;
+
+/// Runs the game logic without a window, a renderer or egui.
+///
+/// Expects `MinimalPlugins`, `RngPlugin` and `RapierPhysicsPlugin` (without its default system
+/// setup) to be added by the app. Adds Tnua's plugins itself, to the fixed timestep schedule like
+/// Rapier's systems. Every call to `App::update` advances the simulation by exactly one fixed step
+/// of [`HEADLESS_TIMESTEP`], so with a seeded `RngPlugin` a match can be replayed
+/// deterministically.
+pub struct HeadlessGamePlugin;
+
+pub const HEADLESS_TIMESTEP: f32 = crate::GAMEPLAY_TIMESTEP;
+
+/// Marks the app as headless. Systems that spawn visuals check for it.
+#[derive(Resource)]
+pub struct Headless;
+
+impl Plugin for HeadlessGamePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Headless);
+
+        if !app.is_plugin_added::<TransformPlugin>() {
+            app.add_plugin(TransformPlugin);
+        }
+        if !app.is_plugin_added::<HierarchyPlugin>() {
+            app.add_plugin(HierarchyPlugin);
+        }
+        if !app.is_plugin_added::<AssetPlugin>() {
+            app.add_plugin(AssetPlugin::default());
+        }
+        // Rapier needs the mesh storage for its async colliders, even if we never use them.
+        app.add_asset::<Mesh>();
+
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+            HEADLESS_TIMESTEP,
+        )));
+
+        crate::add_gameplay_plugins(app);
+
+        app.add_system(start_match.in_set(OnUpdate(AppState::MainMenu)));
+    }
+}
+
+fn start_match(mut state: ResMut<NextState<AppState>>) {
+    state.set(AppState::LoadLevel);
+}
+
+/// Loads assets that are only used for presentation. When headless, returns default handles
+/// instead so that nothing gets loaded.
+#[derive(SystemParam)]
+pub struct VisualAssets<'w> {
+    asset_server: Res<'w, AssetServer>,
+    headless: Option<Res<'w, Headless>>,
+}
+
+impl VisualAssets<'_> {
+    pub fn load<T: Asset>(&self, path: &str) -> Handle<T> {
+        if self.headless.is_some() {
+            Handle::default()
+        } else {
+            self.asset_server.load(path)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MatchSummary {
+    pub state: AppState,
+    pub outcome: LevelOutcome,
+    pub remaining_alive: usize,
+    pub player_alive: bool,
+    pub scores: Vec<(String, usize)>,
+}
+
+pub fn summarize_match(world: &mut World) -> MatchSummary {
+    let state = world.resource::<State<AppState>>().0.clone();
+    let outcome = *world.resource::<LevelOutcome>();
+    let mut killables_query = world.query::<(&Killable, Option<&IsPlayer>)>();
+    let mut remaining_alive = 0;
+    let mut player_alive = false;
+    for (killable, is_player) in killables_query.iter(world) {
+        if !killable.killed {
+            remaining_alive += 1;
+            if is_player.is_some() {
+                player_alive = true;
+            }
+        }
+    }
+    let scores = world
+        .query::<&ScoreHaver>()
+        .iter(world)
+        .map(|score_haver| (score_haver.name().to_owned(), score_haver.score))
+        .collect();
+    MatchSummary {
+        state,
+        outcome,
+        remaining_alive,
+        player_alive,
+        scores,
+    }
+}
@@ -0,0 +1,238 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::killing::Killable;
+use crate::level_reloading::CleanOnLevelReload;
+use crate::{collision_groups, GameplaySet, PostPhysicsPropagateSet};
+
+pub struct HitZonesPlugin;
+
+impl Plugin for HitZonesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(attach_hitboxes);
+        app.add_system({
+            follow_bones
+                .after(PostPhysicsPropagateSet)
+                .before(GameplaySet)
+                .in_schedule(CoreSchedule::FixedUpdate)
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HitZone {
+    Head,
+    Torso,
+    Arm,
+    Leg,
+}
+
+impl HitZone {
+    pub fn damage_multiplier(&self) -> f32 {
+        match self {
+            HitZone::Head => 2.0,
+            HitZone::Torso => 1.0,
+            HitZone::Arm => 0.5,
+            HitZone::Leg => 0.6,
+        }
+    }
+}
+
+struct HitboxSpec {
+    bone: &'static str,
+    zone: HitZone,
+    length: f32,
+    radius: f32,
+}
+
+/// The bones of `human.glb` that get hitboxes. Bones point along their local Y axis.
+const HUMAN_HITBOXES: &[HitboxSpec] = &[
+    HitboxSpec {
+        bone: "Bone.001",
+        zone: HitZone::Torso,
+        length: 0.4,
+        radius: 0.5,
+    },
+    HitboxSpec {
+        bone: "Bone.002",
+        zone: HitZone::Torso,
+        length: 0.4,
+        radius: 0.5,
+    },
+    HitboxSpec {
+        bone: "Bone.003",
+        zone: HitZone::Head,
+        length: 0.4,
+        radius: 0.35,
+    },
+    HitboxSpec {
+        bone: "Bone.014",
+        zone: HitZone::Arm,
+        length: 0.5,
+        radius: 0.15,
+    },
+    HitboxSpec {
+        bone: "Bone.016",
+        zone: HitZone::Arm,
+        length: 0.3,
+        radius: 0.15,
+    },
+    HitboxSpec {
+        bone: "Bone.015",
+        zone: HitZone::Arm,
+        length: 0.5,
+        radius: 0.15,
+    },
+    HitboxSpec {
+        bone: "Bone.018",
+        zone: HitZone::Arm,
+        length: 0.3,
+        radius: 0.15,
+    },
+    HitboxSpec {
+        bone: "Bone.008",
+        zone: HitZone::Leg,
+        length: 1.0,
+        radius: 0.2,
+    },
+    HitboxSpec {
+        bone: "Bone.010",
+        zone: HitZone::Leg,
+        length: 1.0,
+        radius: 0.2,
+    },
+    HitboxSpec {
+        bone: "Bone.009",
+        zone: HitZone::Leg,
+        length: 1.0,
+        radius: 0.2,
+    },
+    HitboxSpec {
+        bone: "Bone.011",
+        zone: HitZone::Leg,
+        length: 1.0,
+        radius: 0.2,
+    },
+];
+
+/// A sensor collider that follows a bone of a [`Killable`]'s model.
+///
+/// Hitboxes are not part of their owner's rigid body - Rapier does not move child colliders when
+/// their bone is animated - so they are kept at the top level and moved every step instead. They
+/// don't generate collision events, and are only found by [`HitZoneDetector`]'s raycasts.
+#[derive(Component)]
+pub struct Hitbox {
+    pub owner: Entity,
+    pub zone: HitZone,
+    bone: Entity,
+    offset: Transform,
+}
+
+#[derive(Component)]
+struct HitboxesAttached;
+
+fn attach_hitboxes(
+    owners_query: Query<Entity, (With<Killable>, Without<HitboxesAttached>)>,
+    children_query: Query<&Children>,
+    names_query: Query<&Name>,
+    mut commands: Commands,
+) {
+    for owner in owners_query.iter() {
+        let bones = children_query
+            .iter_descendants(owner)
+            .filter_map(|entity| {
+                let name = names_query.get(entity).ok()?;
+                let spec = HUMAN_HITBOXES
+                    .iter()
+                    .find(|spec| spec.bone == name.as_str())?;
+                Some((entity, spec))
+            })
+            .collect::<Vec<_>>();
+        // The model's scene was not spawned yet (or never will, when running headless)
+        if bones.is_empty() {
+            continue;
+        }
+        commands.entity(owner).insert(HitboxesAttached);
+        for (bone, spec) in bones {
+            let mut cmd = commands.spawn_empty();
+            cmd.insert(CleanOnLevelReload);
+            cmd.insert(TransformBundle::default());
+            cmd.insert(Collider::capsule_y(0.5 * spec.length, spec.radius));
+            cmd.insert(Sensor);
+            cmd.insert(CollisionGroups {
+                memberships: collision_groups::PARTICIPANT,
+                filters: Group::NONE,
+            });
+            cmd.insert(Hitbox {
+                owner,
+                zone: spec.zone,
+                bone,
+                offset: Transform::from_xyz(0.0, 0.5 * spec.length, 0.0),
+            });
+        }
+    }
+}
+
+fn follow_bones(
+    mut hitboxes_query: Query<(Entity, &Hitbox, &mut Transform, &mut GlobalTransform)>,
+    bones_query: Query<&GlobalTransform, Without<Hitbox>>,
+    mut commands: Commands,
+) {
+    for (entity, hitbox, mut transform, mut global_transform) in hitboxes_query.iter_mut() {
+        let Ok(bone_transform) = bones_query.get(hitbox.bone) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+        *global_transform = bone_transform.mul_transform(hitbox.offset);
+        *transform = global_transform.compute_transform();
+    }
+}
+
+/// How far back along the bullet's path to look for the hitbox it went through.
+const HIT_ZONE_PROBE_DISTANCE: f32 = 3.0;
+
+#[derive(SystemParam)]
+pub struct HitZoneDetector<'w, 's> {
+    rapier_context: Res<'w, RapierContext>,
+    hitboxes_query: Query<'w, 's, &'static Hitbox>,
+    transforms_query: Query<'w, 's, &'static GlobalTransform>,
+}
+
+impl HitZoneDetector<'_, '_> {
+    /// Find which part of the victim a bullet that hit it at `position` went through.
+    pub fn detect(&self, victim: Entity, position: Vec3, velocity: Vec3) -> Option<HitZone> {
+        let direction = velocity.normalize_or_zero();
+        if let Some((hitbox, _)) = self.rapier_context.cast_ray(
+            position - HIT_ZONE_PROBE_DISTANCE * direction,
+            direction,
+            2.0 * HIT_ZONE_PROBE_DISTANCE,
+            true,
+            QueryFilter::default().predicate(&|entity| {
+                self.hitboxes_query
+                    .get(entity)
+                    .map_or(false, |hitbox| hitbox.owner == victim)
+            }),
+        ) {
+            if let Ok(hitbox) = self.hitboxes_query.get(hitbox) {
+                return Some(hitbox.zone);
+            }
+        }
+
+        // No hitboxes (e.g. when headless) or the bullet missed them all - guess by the height
+        // at which it hit the body's capsule.
+        let victim_transform = self.transforms_query.get(victim).ok()?;
+        let height = victim_transform
+            .affine()
+            .inverse()
+            .transform_point3(position)
+            .y;
+        Some(if 0.9 < height {
+            HitZone::Head
+        } else if height < -0.3 {
+            HitZone::Leg
+        } else {
+            HitZone::Torso
+        })
+    }
+}
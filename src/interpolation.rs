@@ -0,0 +1,85 @@
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use bevy_rapier3d::prelude::*;
+
+use crate::GameplaySet;
+
+/// Physics and gameplay run on the fixed timestep, which does not match the rendering rate. This
+/// plugin renders the rigid bodies at a pose interpolated between the last two steps, and puts
+/// them back at their real pose before the next step.
+pub struct PhysicsInterpolationPlugin;
+
+impl Plugin for PhysicsInterpolationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system({
+            restore_physics_transforms
+                .before(PhysicsSet::SyncBackend)
+                .in_schedule(CoreSchedule::FixedUpdate)
+        });
+        app.add_system({
+            record_physics_transforms
+                .after(PhysicsSet::Writeback)
+                .after(GameplaySet)
+                .in_schedule(CoreSchedule::FixedUpdate)
+        });
+        app.add_system({
+            interpolate_transforms
+                .in_set(PhysicsInterpolationSet)
+                .in_base_set(CoreSet::PostUpdate)
+                .before(TransformSystem::TransformPropagate)
+        });
+    }
+}
+
+/// Runs in `CoreSet::PostUpdate`. Systems that follow rigid bodies visually should run after it.
+#[derive(SystemSet, Clone, PartialEq, Eq, Debug, Hash)]
+pub struct PhysicsInterpolationSet;
+
+#[derive(Component)]
+struct PhysicsInterpolation {
+    previous: Transform,
+    current: Transform,
+}
+
+fn restore_physics_transforms(mut query: Query<(&mut Transform, &PhysicsInterpolation)>) {
+    for (mut transform, interpolation) in query.iter_mut() {
+        if *transform != interpolation.current {
+            *transform = interpolation.current;
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn record_physics_transforms(
+    mut commands: Commands,
+    mut query: Query<
+        (Entity, &Transform, Option<&mut PhysicsInterpolation>),
+        (With<RigidBody>, Without<Parent>),
+    >,
+) {
+    for (entity, transform, interpolation) in query.iter_mut() {
+        if let Some(mut interpolation) = interpolation {
+            interpolation.previous = interpolation.current;
+            interpolation.current = *transform;
+        } else {
+            commands.entity(entity).insert(PhysicsInterpolation {
+                previous: *transform,
+                current: *transform,
+            });
+        }
+    }
+}
+
+fn interpolate_transforms(
+    fixed_time: Res<FixedTime>,
+    mut query: Query<(&mut Transform, &PhysicsInterpolation)>,
+) {
+    let alpha = (fixed_time.accumulated().as_secs_f32() / fixed_time.period.as_secs_f32()).min(1.0);
+    for (mut transform, PhysicsInterpolation { previous, current }) in query.iter_mut() {
+        if previous == current {
+            continue;
+        }
+        transform.translation = previous.translation.lerp(current.translation, alpha);
+        transform.rotation = previous.rotation.slerp(current.rotation, alpha);
+    }
+}
@@ -2,30 +2,281 @@ use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 use bevy_tnua::{TnuaMotor, TnuaPlatformerControls};
 
-use crate::bullet::Bullet;
-use crate::bumpin::BumpStatus;
-use crate::collision_groups;
-use crate::menu::AppState;
+use crate::bullet::{BulletImpact, BulletImpactSet, BulletImpactTargetKind};
+use crate::bumpin::{BumpEvent, BumpSet, BumpStatus};
+use crate::hit_zones::{HitZone, HitZoneDetector};
+use crate::level::HealthRules;
 use crate::score::ScoreHaver;
-use crate::utils::entities_ordered_by_type;
+use crate::{collision_groups, GameplaySet};
 
 pub struct KillingPlugin;
 
 impl Plugin for KillingPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(handle_bullet_hits.in_set(OnUpdate(AppState::Game)));
+        app.add_event::<DamageRequest>();
+        app.add_event::<DamageEvent>();
+        app.add_event::<DeathEvent>();
+        app.add_systems(
+            (
+                handle_bullet_hits.after(BulletImpactSet),
+                handle_bump_damage.after(BumpSet),
+                handle_fall_damage,
+                apply_damage,
+                handle_deaths,
+            )
+                .chain()
+                .in_set(KillingSet)
+                .in_set(GameplaySet)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
     }
 }
 
+/// Systems that apply damage and send [`DamageEvent`]s and [`DeathEvent`]s.
+#[derive(SystemSet, Clone, PartialEq, Eq, Debug, Hash)]
+pub struct KillingSet;
+
 #[derive(Component)]
 pub struct Killable {
     pub killed: bool,
 }
 
-#[allow(clippy::type_complexity)]
+/// Only added when the level's [`DamageRules`](crate::level::DamageRules) use health. Without it,
+/// any damage kills.
+#[derive(Component)]
+pub struct Health {
+    pub current: f32,
+    rules: HealthRules,
+    invulnerable: Option<Timer>,
+    /// The fastest downward speed since the participant last stood or moved up.
+    fall_speed: f32,
+}
+
+impl Health {
+    pub fn new(rules: HealthRules) -> Self {
+        Self {
+            current: rules.max_health,
+            rules,
+            invulnerable: None,
+            fall_speed: 0.0,
+        }
+    }
+
+    pub fn max(&self) -> f32 {
+        self.rules.max_health
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DamageSource {
+    Bullet {
+        shooter: Entity,
+        /// Where the bullet hit.
+        position: Vec3,
+        momentum: Vec3,
+    },
+    Bump {
+        initiator: Entity,
+    },
+    Fall,
+}
+
+impl DamageSource {
+    /// The participant that gets the credit if the damage kills.
+    pub fn killer(&self) -> Option<Entity> {
+        match self {
+            DamageSource::Bullet { shooter, .. } => Some(*shooter),
+            DamageSource::Bump { initiator } => Some(*initiator),
+            DamageSource::Fall => None,
+        }
+    }
+}
+
+/// Extra score for killing with a headshot.
+const HEADSHOT_BONUS: usize = 1;
+
+/// Damage that was not yet checked against the victim's state.
+struct DamageRequest {
+    victim: Entity,
+    source: DamageSource,
+    amount: f32,
+    hit_zone: Option<HitZone>,
+}
+
+/// Sent when a participant takes damage.
+#[derive(Debug)]
+pub struct DamageEvent {
+    pub victim: Entity,
+    pub source: DamageSource,
+    pub amount: f32,
+    /// Only for bullets.
+    pub hit_zone: Option<HitZone>,
+}
+
+/// Sent when damage kills a participant.
+#[derive(Debug)]
+pub struct DeathEvent {
+    pub victim: Entity,
+    pub source: DamageSource,
+    pub hit_zone: Option<HitZone>,
+}
+
 fn handle_bullet_hits(
-    mut reader: EventReader<CollisionEvent>,
-    bullets_query: Query<&Bullet>,
+    mut reader: EventReader<BulletImpact>,
+    hit_zone_detector: HitZoneDetector,
+    rapier_context: Res<RapierContext>,
+    mut impulses_query: Query<Option<&mut ExternalImpulse>, With<RigidBody>>,
+    mut commands: Commands,
+    mut damage_writer: EventWriter<DamageRequest>,
+) {
+    for impact in reader.iter() {
+        if let BulletImpactTargetKind::Level(_) = impact.target_kind {
+            continue;
+        }
+        // Push the target away from the shooter, spinning it according to where it was hit. This
+        // also pushes dead bodies and loose rifles.
+        if let Ok(external_impulse) = impulses_query.get_mut(impact.target) {
+            let center_of_mass = rapier_context
+                .entity2body()
+                .get(&impact.target)
+                .and_then(|handle| rapier_context.bodies.get(*handle))
+                .map_or(impact.position, |body| (*body.center_of_mass()).into());
+            let impulse =
+                ExternalImpulse::at_point(impact.momentum(), impact.position, center_of_mass);
+            if let Some(mut external_impulse) = external_impulse {
+                external_impulse.impulse += impulse.impulse;
+                external_impulse.torque_impulse += impulse.torque_impulse;
+            } else {
+                commands.entity(impact.target).insert(impulse);
+            }
+        }
+
+        if impact.target_kind != BulletImpactTargetKind::Killable {
+            continue;
+        }
+        let hit_zone = hit_zone_detector.detect(impact.target, impact.position, impact.velocity);
+        damage_writer.send(DamageRequest {
+            victim: impact.target,
+            source: DamageSource::Bullet {
+                shooter: impact.shooter,
+                position: impact.position,
+                momentum: impact.momentum(),
+            },
+            amount: impact.damage * hit_zone.map_or(1.0, |zone| zone.damage_multiplier()),
+            hit_zone,
+        });
+    }
+}
+
+fn handle_bump_damage(
+    mut reader: EventReader<BumpEvent>,
+    health_query: Query<&Health>,
+    mut damage_writer: EventWriter<DamageRequest>,
+) {
+    for BumpEvent {
+        initiator,
+        victim,
+        impulse,
+    } in reader.iter()
+    {
+        // Without health, bumps are harmless
+        let Ok(health) = health_query.get(*victim) else { continue };
+        let excess = impulse.length() - health.rules.bump_damage_threshold;
+        if 0.0 < excess {
+            damage_writer.send(DamageRequest {
+                victim: *victim,
+                source: DamageSource::Bump {
+                    initiator: *initiator,
+                },
+                amount: excess * health.rules.bump_damage_per_impulse,
+                hit_zone: None,
+            });
+        }
+    }
+}
+
+fn handle_fall_damage(
+    mut query: Query<(Entity, &mut Health, &Velocity)>,
+    mut damage_writer: EventWriter<DamageRequest>,
+) {
+    for (entity, mut health, velocity) in query.iter_mut() {
+        let vertical_velocity = velocity.linvel.y;
+        if vertical_velocity < 0.0 {
+            health.fall_speed = health.fall_speed.max(-vertical_velocity);
+            continue;
+        }
+        // The fall has stopped - the participant has landed
+        let excess = health.fall_speed - health.rules.safe_fall_speed;
+        health.fall_speed = 0.0;
+        if 0.0 < excess {
+            damage_writer.send(DamageRequest {
+                victim: entity,
+                source: DamageSource::Fall,
+                amount: excess * health.rules.fall_damage_per_speed,
+                hit_zone: None,
+            });
+        }
+    }
+}
+
+fn apply_damage(
+    fixed_time: Res<FixedTime>,
+    mut reader: EventReader<DamageRequest>,
+    mut victims_query: Query<(&Killable, Option<&mut Health>)>,
+    mut damage_writer: EventWriter<DamageEvent>,
+    mut death_writer: EventWriter<DeathEvent>,
+) {
+    for (_, health) in victims_query.iter_mut() {
+        let Some(mut health) = health else { continue };
+        if let Some(invulnerable) = health.invulnerable.as_mut() {
+            if invulnerable.tick(fixed_time.period).finished() {
+                health.invulnerable = None;
+            }
+        }
+    }
+    for DamageRequest {
+        victim,
+        source,
+        amount,
+        hit_zone,
+    } in reader.iter()
+    {
+        let Ok((killable, health)) = victims_query.get_mut(*victim) else { continue };
+        if killable.killed {
+            continue;
+        }
+        let killed = if let Some(mut health) = health {
+            if health.invulnerable.is_some() || health.current <= 0.0 {
+                continue;
+            }
+            health.current -= amount;
+            health.invulnerable = Some(Timer::from_seconds(
+                health.rules.invulnerability,
+                TimerMode::Once,
+            ));
+            health.current <= 0.0
+        } else {
+            true
+        };
+        damage_writer.send(DamageEvent {
+            victim: *victim,
+            source: *source,
+            amount: *amount,
+            hit_zone: *hit_zone,
+        });
+        if killed {
+            death_writer.send(DeathEvent {
+                victim: *victim,
+                source: *source,
+                hit_zone: *hit_zone,
+            });
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn handle_deaths(
+    mut reader: EventReader<DeathEvent>,
     mut victims_query: Query<(
         &mut Killable,
         &mut LockedAxes,
@@ -35,41 +286,52 @@ fn handle_bullet_hits(
     )>,
     mut commands: Commands,
     mut score_havers_query: Query<&mut ScoreHaver>,
-    mut state: ResMut<NextState<AppState>>,
 ) {
-    for event in reader.iter() {
-        let CollisionEvent::Started(e1, e2, _) = event else { continue };
-        let Some([bullet, victim]) = entities_ordered_by_type!([*e1, *e2], bullets_query, victims_query) else { continue };
-        let Bullet { shooter } = bullets_query.get(bullet).unwrap();
-        if *shooter == victim {
-            continue;
-        }
-
-        let (mut killable, mut locked_axes, mut solver_groups, transform, mut velocity) =
-            victims_query.get_mut(victim).unwrap();
+    for DeathEvent {
+        victim,
+        source,
+        hit_zone,
+    } in reader.iter()
+    {
+        let Ok((mut killable, mut locked_axes, mut solver_groups, transform, mut velocity)) = victims_query.get_mut(*victim) else { continue };
         if killable.killed {
             continue;
         }
         killable.killed = true;
         commands
-            .entity(victim)
+            .entity(*victim)
             .remove::<(TnuaPlatformerControls, TnuaMotor, BumpStatus)>();
         *locked_axes = Default::default();
         solver_groups.filters = collision_groups::GENERAL;
-        velocity.linvel = Vec3::Y * 3.0;
-        velocity.angvel = Quat::from_axis_angle(transform.right(), 1.0).xyz();
-
-        if let Ok(mut score_haver) = score_havers_query.get_mut(*shooter) {
-            score_haver.score += 1;
+        // The bullet's momentum from `handle_bullet_hits` is applied on top of this in the next
+        // physics step
+        match hit_zone {
+            Some(HitZone::Head) => {
+                // Thrown backwards
+                velocity.linvel = Vec3::Y * 3.0 + transform.back() * 2.0;
+                velocity.angvel = Quat::from_axis_angle(transform.right(), 3.0).xyz();
+            }
+            Some(HitZone::Leg) => {
+                // Legs give way and the body topples sideways
+                velocity.linvel = Vec3::ZERO;
+                velocity.angvel = Quat::from_axis_angle(transform.forward(), 1.0).xyz();
+            }
+            _ => {
+                velocity.linvel = Vec3::Y * 3.0;
+                velocity.angvel = Quat::from_axis_angle(transform.right(), 1.0).xyz();
+            }
         }
 
-        let remaining_alive = victims_query
-            .iter()
-            .filter(|(killable, _, _, _, _)| !killable.killed)
-            .count();
-
-        if remaining_alive <= 1 {
-            state.set(AppState::GameOver);
+        let Some(killer) = source.killer() else { continue };
+        if killer == *victim {
+            continue;
+        }
+        if let Ok(mut score_haver) = score_havers_query.get_mut(killer) {
+            score_haver.score += 1;
+            if *hit_zone == Some(HitZone::Head) {
+                score_haver.score += HEADSHOT_BONUS;
+            }
+            score_haver.kills += 1;
         }
     }
 }
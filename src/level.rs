@@ -0,0 +1,330 @@
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use serde::{Deserialize, Serialize};
+
+use crate::bullet::ProjectileModel;
+use crate::difficulty::Difficulty;
+use crate::weapon::DEFAULT_WEAPON;
+
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Level>();
+        app.add_asset_loader(LevelLoader);
+        app.add_asset::<LevelIndex>();
+        app.add_asset_loader(LevelIndexLoader);
+        app.init_resource::<CurrentLevel>();
+        app.init_resource::<AvailableLevels>();
+    }
+}
+
+/// The level that [`LevelPopulationSet`](crate::level_reloading::LevelPopulationSet) populates
+/// the world from.
+#[derive(Resource)]
+pub struct CurrentLevel {
+    pub handle: Handle<Level>,
+    /// Position of the level in the [`LevelIndex`], if it was selected from there.
+    pub index_entry: Option<usize>,
+}
+
+impl FromWorld for CurrentLevel {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            handle: world
+                .resource::<AssetServer>()
+                .load("levels/arena.level.ron"),
+            index_entry: None,
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct AvailableLevels(pub Handle<LevelIndex>);
+
+impl FromWorld for AvailableLevels {
+    fn from_world(world: &mut World) -> Self {
+        Self(
+            world
+                .resource::<AssetServer>()
+                .load("levels/levels.index.ron"),
+        )
+    }
+}
+
+/// The list of levels offered in the menus. Files are relative to the `levels` directory.
+#[derive(TypeUuid, Serialize, Deserialize, Debug, Clone)]
+#[uuid = "5c1f8f0e-4f0b-4d7e-9a57-0e6a7f3c2b14"]
+pub struct LevelIndex {
+    pub levels: Vec<LevelIndexEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LevelIndexEntry {
+    pub name: String,
+    pub file: String,
+}
+
+#[derive(SystemParam)]
+pub struct LevelSelection<'w> {
+    asset_server: Res<'w, AssetServer>,
+    available_levels: Res<'w, AvailableLevels>,
+    level_indices: ResMut<'w, Assets<LevelIndex>>,
+    current_level: ResMut<'w, CurrentLevel>,
+}
+
+impl LevelSelection<'_> {
+    /// `None` if the index is not loaded yet.
+    pub fn index(&self) -> Option<&LevelIndex> {
+        self.level_indices.get(&self.available_levels.0)
+    }
+
+    /// Add a level file to the index, unless it is already there. Returns the entry's position.
+    pub fn register(&mut self, entry: LevelIndexEntry) -> Option<usize> {
+        let index = self.level_indices.get_mut(&self.available_levels.0)?;
+        if let Some(existing) = index.levels.iter().position(|e| e.file == entry.file) {
+            return Some(existing);
+        }
+        index.levels.push(entry);
+        Some(index.levels.len() - 1)
+    }
+
+    pub fn select(&mut self, entry: usize) -> bool {
+        let Some(index) = self.level_indices.get(&self.available_levels.0) else { return false };
+        let Some(LevelIndexEntry { file, .. }) = index.levels.get(entry) else { return false };
+        let path = format!("levels/{}", file);
+        *self.current_level = CurrentLevel {
+            handle: self.asset_server.load(path.as_str()),
+            index_entry: Some(entry),
+        };
+        true
+    }
+
+    pub fn current(&self) -> &CurrentLevel {
+        &self.current_level
+    }
+
+    /// Use a level that was not loaded from a file, keeping the index entry so that saving and
+    /// "Next Level" still refer to the original file.
+    pub fn replace_current(&mut self, handle: Handle<Level>) {
+        self.current_level.handle = handle;
+    }
+
+    pub fn next_entry(&self) -> Option<usize> {
+        let next_entry = self.current_level.index_entry? + 1;
+        if next_entry < self.index()?.levels.len() {
+            Some(next_entry)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(TypeUuid, Serialize, Deserialize, Debug, Clone)]
+#[uuid = "b2d8a3bb-cbef-437a-9a04-dffa7190cf15"]
+pub struct Level {
+    pub blocks: Vec<LevelBlock>,
+    pub player: PlayerSpawn,
+    pub opponents: Vec<OpponentSpawn>,
+    pub rifles: Vec<RifleSpawn>,
+    #[serde(default)]
+    pub rules: LevelRules,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LevelBlock {
+    pub kind: LevelBlockKind,
+    /// Center of the box.
+    pub position: Vec3,
+    /// Full size of the box (not half-extents).
+    pub size: Vec3,
+    /// Rotation around the up axis, in radians.
+    #[serde(default)]
+    pub yaw: f32,
+}
+
+#[derive(Component, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LevelBlockKind {
+    Ground,
+    Wall,
+    Obstacle,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlayerSpawn {
+    pub position: Vec3,
+    #[serde(default = "default_facing")]
+    pub facing: Vec3,
+}
+
+fn default_facing() -> Vec3 {
+    -Vec3::Z
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OpponentSpawn {
+    pub position: Vec3,
+    #[serde(default)]
+    pub config: OpponentConfig,
+    /// Overrides the difficulty selected in the menu.
+    #[serde(default)]
+    pub difficulty: Option<Difficulty>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct OpponentConfig {
+    pub full_speed: f32,
+    pub full_jump_height: f32,
+    pub turning_angvel: f32,
+}
+
+impl Default for OpponentConfig {
+    fn default() -> Self {
+        Self {
+            full_speed: 20.0,
+            full_jump_height: 4.0,
+            turning_angvel: 5.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RifleSpawn {
+    pub position: Vec3,
+    /// The rifle is placed randomly up to this distance (on each horizontal axis) from
+    /// `position`.
+    #[serde(default)]
+    pub scatter: f32,
+    #[serde(default)]
+    pub projectile: ProjectileModel,
+    /// File of the rifle's [`WeaponDefinition`](crate::weapon::WeaponDefinition), relative to
+    /// the `weapons` directory.
+    #[serde(default = "default_weapon")]
+    pub weapon: String,
+}
+
+fn default_weapon() -> String {
+    DEFAULT_WEAPON.to_owned()
+}
+
+/// The level is won as soon as any of the win conditions is met, and lost as soon as any of the
+/// lose conditions is met. If neither happens, the level ends when at most one participant remains
+/// alive.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LevelRules {
+    pub win_conditions: Vec<WinCondition>,
+    pub lose_conditions: Vec<LoseCondition>,
+    pub damage: DamageRules,
+}
+
+impl Default for LevelRules {
+    fn default() -> Self {
+        Self {
+            win_conditions: vec![WinCondition::LastAlive],
+            lose_conditions: vec![],
+            damage: DamageRules::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub enum DamageRules {
+    /// Any bullet kills instantly. Bumps and falls are harmless.
+    #[default]
+    OneHitKill,
+    /// Participants get a [`Health`](crate::killing::Health) component.
+    Health(HealthRules),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HealthRules {
+    pub max_health: f32,
+    /// Seconds after taking damage during which no further damage is taken.
+    pub invulnerability: f32,
+    /// Bumps weaker than this impulse are harmless.
+    pub bump_damage_threshold: f32,
+    /// Damage per unit of bump impulse above the threshold.
+    pub bump_damage_per_impulse: f32,
+    /// Landing slower than this vertical speed is harmless.
+    pub safe_fall_speed: f32,
+    /// Damage per unit of landing speed above the safe speed.
+    pub fall_damage_per_speed: f32,
+}
+
+impl Default for HealthRules {
+    fn default() -> Self {
+        Self {
+            max_health: 100.0,
+            invulnerability: 0.5,
+            bump_damage_threshold: 20.0,
+            bump_damage_per_impulse: 1.0,
+            safe_fall_speed: 20.0,
+            fall_damage_per_speed: 3.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum WinCondition {
+    /// The player is the only one left alive.
+    LastAlive,
+    /// The player's score reaches the given value.
+    ScoreAtLeast(usize),
+    /// The player killed at least this many opponents.
+    Kills(usize),
+    /// The player stays alive for this many seconds.
+    SurviveFor(f32),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum LoseCondition {
+    PlayerKilled,
+    /// The level was not won within this many seconds.
+    TimeLimit(f32),
+}
+
+struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let level: Level = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(level));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
+struct LevelIndexLoader;
+
+impl AssetLoader for LevelIndexLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let index: LevelIndex = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(index));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["index.ron"]
+    }
+}
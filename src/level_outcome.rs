@@ -0,0 +1,114 @@
+use bevy::prelude::*;
+use bevy::time::Stopwatch;
+
+use crate::killing::Killable;
+use crate::level::{CurrentLevel, Level, LoseCondition, WinCondition};
+use crate::menu::AppState;
+use crate::player::IsPlayer;
+use crate::score::ScoreHaver;
+use crate::GameplaySet;
+
+pub struct LevelOutcomePlugin;
+
+impl Plugin for LevelOutcomePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LevelOutcome>();
+        app.init_resource::<LevelClock>();
+        app.add_system(reset_level_outcome.in_schedule(OnEnter(AppState::LoadLevel)));
+        app.add_system({
+            evaluate_level_outcome
+                .in_set(GameplaySet)
+                .in_schedule(CoreSchedule::FixedUpdate)
+        });
+    }
+}
+
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LevelOutcome {
+    #[default]
+    Undecided,
+    Win,
+    Loss,
+    Draw,
+}
+
+/// Gameplay time spent in [`AppState::Game`] since the level was loaded.
+#[derive(Resource, Default)]
+pub struct LevelClock(pub Stopwatch);
+
+fn reset_level_outcome(mut outcome: ResMut<LevelOutcome>, mut clock: ResMut<LevelClock>) {
+    *outcome = LevelOutcome::Undecided;
+    clock.0.reset();
+}
+
+fn evaluate_level_outcome(
+    fixed_time: Res<FixedTime>,
+    mut clock: ResMut<LevelClock>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+    killables_query: Query<(&Killable, Option<&IsPlayer>, Option<&ScoreHaver>)>,
+    mut outcome: ResMut<LevelOutcome>,
+    mut state: ResMut<NextState<AppState>>,
+) {
+    let Some(level) = levels.get(&current_level.handle) else { return };
+    let elapsed = clock.0.tick(fixed_time.period).elapsed_secs();
+
+    let mut remaining_alive = 0;
+    let mut player = None;
+    for (killable, is_player, score_haver) in killables_query.iter() {
+        if !killable.killed {
+            remaining_alive += 1;
+        }
+        if is_player.is_some() {
+            player = Some((killable, score_haver));
+        }
+    }
+    let player_alive = matches!(player, Some((Killable { killed: false }, _)));
+    let (score, kills) = match player {
+        Some((_, Some(score_haver))) => (score_haver.score, score_haver.kills),
+        _ => (0, 0),
+    };
+
+    let new_outcome = 'outcome: {
+        if player_alive
+            && level
+                .rules
+                .win_conditions
+                .iter()
+                .any(|condition| match condition {
+                    WinCondition::LastAlive => remaining_alive == 1,
+                    WinCondition::ScoreAtLeast(target) => *target <= score,
+                    WinCondition::Kills(target) => *target <= kills,
+                    WinCondition::SurviveFor(seconds) => *seconds <= elapsed,
+                })
+        {
+            break 'outcome LevelOutcome::Win;
+        }
+        if level
+            .rules
+            .lose_conditions
+            .iter()
+            .any(|condition| match condition {
+                LoseCondition::PlayerKilled => !player_alive,
+                LoseCondition::TimeLimit(seconds) => *seconds <= elapsed,
+            })
+        {
+            break 'outcome LevelOutcome::Loss;
+        }
+        if remaining_alive <= 1 {
+            if player_alive || remaining_alive == 0 {
+                break 'outcome LevelOutcome::Draw;
+            } else {
+                break 'outcome LevelOutcome::Loss;
+            }
+        }
+        LevelOutcome::Undecided
+    };
+
+    *outcome = new_outcome;
+    match new_outcome {
+        LevelOutcome::Undecided => {}
+        LevelOutcome::Win => state.set(AppState::LevelCompleted),
+        LevelOutcome::Loss | LevelOutcome::Draw => state.set(AppState::GameOver),
+    }
+}
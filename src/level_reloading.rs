@@ -1,30 +1,43 @@
 use bevy::prelude::*;
 
+use crate::level::{CurrentLevel, Level};
 use crate::menu::AppState;
+use crate::weapon::level_weapons_loaded;
 
 pub struct LevelReloadingPlugin;
 
 #[derive(Component)]
 pub struct CleanOnLevelReload;
 
+/// Systems that spawn the level's content. They run once, after the [`CurrentLevel`] is loaded.
 #[derive(SystemSet, Clone, PartialEq, Eq, Debug, Hash)]
 pub struct LevelPopulationSet;
 
 impl Plugin for LevelReloadingPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system({
-            clean_entities
-                .in_schedule(OnEnter(AppState::LoadLevel))
-                .before(LevelPopulationSet)
-        });
+        app.add_system(clean_entities.in_schedule(OnEnter(AppState::LoadLevel)));
+        // The editor shows its own previews instead of the level's entities
+        app.add_system(clean_entities.in_schedule(OnEnter(AppState::Editor)));
+        app.configure_set(
+            LevelPopulationSet
+                .in_set(OnUpdate(AppState::LoadLevel))
+                .run_if(current_level_loaded)
+                .run_if(level_weapons_loaded),
+        );
         app.add_system({
             move_to_game_state
-                .in_schedule(OnEnter(AppState::LoadLevel))
+                .in_set(OnUpdate(AppState::LoadLevel))
                 .after(LevelPopulationSet)
+                .run_if(current_level_loaded)
+                .run_if(level_weapons_loaded)
         });
     }
 }
 
+fn current_level_loaded(current_level: Res<CurrentLevel>, levels: Res<Assets<Level>>) -> bool {
+    levels.contains(&current_level.handle)
+}
+
 fn clean_entities(query: Query<Entity, With<CleanOnLevelReload>>, mut commands: Commands) {
     for entity in query.iter() {
         commands.entity(entity).despawn_recursive();
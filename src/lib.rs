@@ -4,19 +4,37 @@ mod bullet;
 mod bumpin;
 mod camera;
 mod crosshair;
+mod difficulty;
+mod editor;
+mod headless;
+mod hit_zones;
+mod interpolation;
 mod killing;
+mod level;
+mod level_outcome;
 mod level_reloading;
+mod line_of_sight;
 mod menu;
+mod navigation;
 mod opponent;
 mod opponent_behavior;
+mod opponent_brain;
 mod player;
+mod ragdoll;
+mod replay;
 mod rifle;
 mod score;
 mod utils;
+mod weapon;
 
 use bevy::prelude::*;
+use bevy::transform::systems::{propagate_transforms, sync_simple_transforms};
 use bevy::window::{CursorGrabMode, PrimaryWindow};
-use bevy_rapier3d::prelude::RapierConfiguration;
+use bevy_rapier3d::prelude::{
+    NoUserData, PhysicsSet, RapierConfiguration, RapierPhysicsPlugin, TimestepMode,
+};
+use bevy_tnua::{TnuaPlatformerPlugin, TnuaRapier3dPlugin, TnuaSystemSet};
+use leafwing_input_manager::prelude::InputManagerPlugin;
 
 use self::animation::GameAnimationPlugin;
 use self::arena::ArenaPlugin;
@@ -24,48 +42,169 @@ use self::bullet::BulletPlugin;
 use self::bumpin::BumpinPlugin;
 use self::camera::GameCameraPlugin;
 use self::crosshair::CrosshairPlugin;
+use self::difficulty::DifficultyPlugin;
+use self::editor::EditorPlugin;
+use self::hit_zones::HitZonesPlugin;
+use self::interpolation::PhysicsInterpolationPlugin;
 use self::killing::KillingPlugin;
+use self::level::LevelPlugin;
+use self::level_outcome::LevelOutcomePlugin;
 use self::level_reloading::LevelReloadingPlugin;
-use self::menu::{AppState, MenuPlugin};
+use self::menu::MenuPlugin;
+use self::navigation::NavigationPlugin;
 use self::opponent::OpponentPlugin;
 use self::opponent_behavior::OpponentBehaviorPlugin;
-use self::player::PlayerPlugin;
+use self::opponent_brain::OpponentBrainPlugin;
+use self::player::{PlayerAction, PlayerPlugin};
+use self::ragdoll::RagdollPlugin;
 
 pub struct GamePlugin;
-pub use self::menu::MenuActionForKbgp;
+pub use self::headless::{summarize_match, HeadlessGamePlugin, MatchSummary, HEADLESS_TIMESTEP};
+pub use self::level_outcome::LevelOutcome;
+pub use self::menu::{AppState, MenuActionForKbgp};
+use self::replay::ReplayPlugin;
+pub use self::replay::{ReplayDivergence, ReplayPlayback, ReplayRecorder};
 use self::rifle::RiflePlugin;
 use self::score::ScorePlugin;
+use self::weapon::WeaponPlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
-        app.add_state::<AppState>();
+        add_gameplay_plugins(app);
+        app.add_plugin(InputManagerPlugin::<PlayerAction>::default());
         app.add_plugin(MenuPlugin);
         app.add_plugin(GameCameraPlugin);
-        app.add_plugin(ArenaPlugin);
-        app.add_plugin(PlayerPlugin);
-        app.add_plugin(OpponentPlugin);
-        app.add_plugin(RiflePlugin);
-        app.add_plugin(LevelReloadingPlugin);
-        app.add_plugin(BumpinPlugin);
-        app.add_plugin(CrosshairPlugin);
-        app.add_plugin(BulletPlugin);
         app.add_plugin(GameAnimationPlugin);
-        app.add_plugin(KillingPlugin);
         app.add_plugin(ScorePlugin);
-        app.add_plugin(OpponentBehaviorPlugin);
+        app.add_plugin(EditorPlugin);
+        app.add_plugin(PhysicsInterpolationPlugin);
+    }
+}
+
+/// The period of `CoreSchedule::FixedUpdate`, where the physics and the gameplay run.
+pub const GAMEPLAY_TIMESTEP: f32 = 1.0 / 60.0;
+
+/// Everything that does not need a window or a renderer. Shared by [`GamePlugin`] and
+/// [`HeadlessGamePlugin`].
+///
+/// Rapier's systems are added to the fixed timestep schedule here, so the app must add
+/// `RapierPhysicsPlugin` with `with_default_system_setup(false)`. Tnua's plugins are added here
+/// too, so the app must not add them.
+fn add_gameplay_plugins(app: &mut App) {
+    app.add_state::<AppState>();
+    app.insert_resource(FixedTime::new_from_secs(GAMEPLAY_TIMESTEP));
+    app.init_resource::<StepTime>();
+    app.insert_resource(RapierConfiguration {
+        timestep_mode: TimestepMode::Fixed {
+            dt: GAMEPLAY_TIMESTEP,
+            substeps: 1,
+        },
+        ..Default::default()
+    });
+    app.edit_schedule(CoreSchedule::FixedUpdate, |schedule| {
+        schedule.configure_sets(
+            (
+                StepTimeSet::SwapIn,
+                TnuaSystemSet::Sensors,
+                TnuaSystemSet::Logic,
+                TnuaSystemSet::Motors,
+                PhysicsSet::SyncBackend,
+                PhysicsSet::SyncBackendFlush,
+                PhysicsSet::StepSimulation,
+                PhysicsSet::Writeback,
+                StepTimeSet::SwapOut,
+            )
+                .chain(),
+        );
+        schedule.add_system(swap_in_step_time.in_set(StepTimeSet::SwapIn));
+        schedule.add_system(swap_out_step_time.in_set(StepTimeSet::SwapOut));
+        for set in [
+            PhysicsSet::SyncBackend,
+            PhysicsSet::SyncBackendFlush,
+            PhysicsSet::StepSimulation,
+            PhysicsSet::Writeback,
+        ] {
+            schedule.add_systems(
+                RapierPhysicsPlugin::<NoUserData>::get_systems(set.clone()).in_base_set(set),
+            );
+        }
 
-        app.add_system(enable_disable_when_in_game_or_not);
+        // So that the gameplay sees the result of the step it runs after
+        schedule.configure_set(
+            PostPhysicsPropagateSet
+                .after(PhysicsSet::Writeback)
+                .before(GameplaySet),
+        );
+        schedule.add_systems(
+            (sync_simple_transforms, propagate_transforms).in_set(PostPhysicsPropagateSet),
+        );
 
-        app.configure_sets(
+        schedule.configure_set(GameplaySet.run_if(in_state(AppState::Game)));
+        schedule.configure_sets(
             (
                 ShootingSequenceSet::ShootInitiator,
                 ShootingSequenceSet::GenerateBullet,
                 ShootingSequenceSet::RifleRecoil,
             )
                 .chain()
-                .in_set(OnUpdate(AppState::Game)),
+                .in_set(GameplaySet),
         );
-    }
+    });
+    // Tnua adds its systems to the default schedule, but they need to run with the physics
+    app.default_schedule_label = Box::new(CoreSchedule::FixedUpdate);
+    app.add_plugin(TnuaRapier3dPlugin);
+    app.add_plugin(TnuaPlatformerPlugin);
+    app.default_schedule_label = Box::new(CoreSchedule::Main);
+    app.add_plugin(LevelPlugin);
+    app.add_plugin(DifficultyPlugin);
+    app.add_plugin(ArenaPlugin);
+    app.add_plugin(NavigationPlugin);
+    app.add_plugin(PlayerPlugin);
+    app.add_plugin(OpponentPlugin);
+    app.add_plugin(RiflePlugin);
+    app.add_plugin(WeaponPlugin);
+    app.add_plugin(LevelReloadingPlugin);
+    app.add_plugin(BumpinPlugin);
+    app.add_plugin(CrosshairPlugin);
+    app.add_plugin(BulletPlugin);
+    app.add_plugin(KillingPlugin);
+    app.add_plugin(HitZonesPlugin);
+    app.add_plugin(RagdollPlugin);
+    app.add_plugin(LevelOutcomePlugin);
+    app.add_plugin(OpponentBehaviorPlugin);
+    app.add_plugin(OpponentBrainPlugin);
+    app.add_plugin(ReplayPlugin);
+
+    app.add_system(enable_disable_when_in_game_or_not);
+}
+
+/// The [`Time`] that systems in `CoreSchedule::FixedUpdate` see while the physics step runs. It
+/// advances by exactly `FixedTime::period` each step, so systems written against `Time` (like
+/// Tnua's) behave the same regardless of the frame rate.
+#[derive(Resource, Default)]
+struct StepTime(Time);
+
+#[derive(SystemSet, Clone, PartialEq, Eq, Debug, Hash)]
+enum StepTimeSet {
+    SwapIn,
+    SwapOut,
+}
+
+fn swap_in_step_time(
+    mut time: ResMut<Time>,
+    mut step_time: ResMut<StepTime>,
+    fixed_time: Res<FixedTime>,
+) {
+    let step_time = &mut step_time.0;
+    let last_step = step_time
+        .last_update()
+        .unwrap_or_else(|| step_time.startup());
+    step_time.update_with_instant(last_step + fixed_time.period);
+    std::mem::swap(time.as_mut(), step_time);
+}
+
+fn swap_out_step_time(mut time: ResMut<Time>, mut step_time: ResMut<StepTime>) {
+    std::mem::swap(time.as_mut(), &mut step_time.0);
 }
 
 fn enable_disable_when_in_game_or_not(
@@ -79,6 +218,8 @@ fn enable_disable_when_in_game_or_not(
         AppState::LoadLevel => (false, true),
         AppState::Game => (true, false),
         AppState::GameOver => (true, true),
+        AppState::LevelCompleted => (true, true),
+        AppState::Editor => (false, true),
     };
     rapier_configuration.physics_pipeline_active = enable_physics;
     if let Ok(mut window) = windows_query.get_single_mut() {
@@ -99,6 +240,20 @@ mod collision_groups {
     pub const WEAPON: Group = Group::GROUP_3;
 }
 
+/// Systems that run in `CoreSchedule::FixedUpdate`, after the physics step, while in
+/// [`AppState::Game`]. Systems in this set should use `FixedTime::period` instead of `Time`.
+///
+/// Systems in this set that draw from `GlobalRng` must be ordered against each other, or the
+/// order of the draws (and with it the replays) will depend on the executor. Currently these are
+/// the opponent decision, then the opponent behavior, then the shooting.
+#[derive(SystemSet, Clone, PartialEq, Eq, Debug, Hash)]
+pub struct GameplaySet;
+
+/// Transform propagation in `CoreSchedule::FixedUpdate`, between the physics step and the
+/// [`GameplaySet`].
+#[derive(SystemSet, Clone, PartialEq, Eq, Debug, Hash)]
+pub struct PostPhysicsPropagateSet;
+
 #[derive(SystemSet, Clone, PartialEq, Eq, Debug, Hash)]
 pub enum ShootingSequenceSet {
     ShootInitiator,
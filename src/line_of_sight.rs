@@ -0,0 +1,63 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::killing::Killable;
+use crate::level::LevelBlockKind;
+
+/// Raycasts for what opponents can see. Only the level and the participants block the view -
+/// rifles, bullets and ragdolls don't.
+#[derive(SystemParam)]
+pub struct LineOfSight<'w, 's> {
+    rapier_context: Res<'w, RapierContext>,
+    occluders_query: Query<'w, 's, (), Or<(With<LevelBlockKind>, With<Killable>)>>,
+}
+
+impl LineOfSight<'_, '_> {
+    /// The first thing that blocks the view from `from` to `to`, if any. `viewer` does not block
+    /// its own view.
+    pub fn first_occluder(&self, viewer: Entity, from: Vec3, to: Vec3) -> Option<Entity> {
+        self.first_occluder_ignoring(&[viewer], from, to)
+    }
+
+    fn first_occluder_ignoring(&self, ignored: &[Entity], from: Vec3, to: Vec3) -> Option<Entity> {
+        let vector = to - from;
+        let distance = vector.length();
+        if distance == 0.0 {
+            return None;
+        }
+        let (occluder, _) = self.rapier_context.cast_ray(
+            from,
+            vector / distance,
+            distance,
+            true,
+            QueryFilter::default()
+                .exclude_sensors()
+                .predicate(&|entity| {
+                    !ignored.contains(&entity) && self.occluders_query.contains(entity)
+                }),
+        )?;
+        Some(occluder)
+    }
+
+    /// Whether `viewer`, looking from `from`, can see `target` (which is at `target_position`).
+    pub fn can_see(
+        &self,
+        viewer: Entity,
+        from: Vec3,
+        target: Entity,
+        target_position: Vec3,
+    ) -> bool {
+        match self.first_occluder(viewer, from, target_position) {
+            None => true,
+            Some(occluder) => occluder == target,
+        }
+    }
+
+    /// Whether `hider` would be out of `viewer`'s sight (looking from `from`) at `position`.
+    /// `hider` itself does not count as cover, since it'd be the one moving there.
+    pub fn is_hidden(&self, viewer: Entity, from: Vec3, hider: Entity, position: Vec3) -> bool {
+        self.first_occluder_ignoring(&[viewer, hider], from, position)
+            .is_some()
+    }
+}
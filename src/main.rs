@@ -9,9 +9,8 @@ use bevy::window::WindowResolution;
 use bevy_egui::{EguiPlugin, EguiSettings};
 use bevy_egui_kbgp::{KbgpNavBindings, KbgpNavCommand, KbgpPlugin, KbgpSettings};
 use bevy_rapier3d::prelude::{NoUserData, RapierPhysicsPlugin};
-use bevy_tnua::{TnuaPlatformerPlugin, TnuaRapier3dPlugin};
 use bevy_turborand::RngPlugin;
-use round_robin_rifle::{GamePlugin, MenuActionForKbgp};
+use round_robin_rifle::{GamePlugin, MenuActionForKbgp, ReplayPlayback, ReplayRecorder};
 
 fn main() {
     let mut app = App::new();
@@ -54,12 +53,44 @@ fn main() {
         },
     });
 
-    app.add_plugin(RapierPhysicsPlugin::<NoUserData>::default());
-    app.add_plugin(TnuaRapier3dPlugin);
+    // The game adds Rapier's systems to the fixed timestep schedule itself, and Tnua's plugins
+    // along with them
+    app.add_plugin(RapierPhysicsPlugin::<NoUserData>::default().with_default_system_setup(false));
     // app.add_plugin(bevy_rapier3d::render::RapierDebugRenderPlugin::default());
-    app.add_plugin(TnuaPlatformerPlugin);
 
     app.add_plugin(GamePlugin);
 
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record-replay" => {
+                let path = args
+                    .next()
+                    .unwrap_or_else(|| exit_with_usage("--record-replay needs a file"));
+                app.insert_resource(ReplayRecorder::new(path));
+            }
+            "--play-replay" => {
+                let path = args
+                    .next()
+                    .unwrap_or_else(|| exit_with_usage("--play-replay needs a file"));
+                match ReplayPlayback::load(&path) {
+                    Ok(playback) => app.insert_resource(playback),
+                    Err(err) => {
+                        exit_with_usage(format!("Unable to load replay {:?}: {}", path, err))
+                    }
+                };
+            }
+            _ => exit_with_usage(format!("Unknown argument {:?}", arg)),
+        }
+    }
+
     app.run();
 }
+
+const USAGE: &str = "Usage: round-robin-rifle [--record-replay FILE | --play-replay FILE]";
+
+fn exit_with_usage(message: impl std::fmt::Display) -> ! {
+    error!("{}", message);
+    error!("{}", USAGE);
+    std::process::exit(1);
+}
@@ -2,7 +2,10 @@ use bevy::prelude::*;
 use bevy_egui::{egui, EguiContexts};
 use bevy_egui_kbgp::prelude::*;
 
+use crate::difficulty::SelectedDifficulty;
 use crate::killing::Killable;
+use crate::level::LevelSelection;
+use crate::level_outcome::LevelOutcome;
 use crate::player::IsPlayer;
 
 #[derive(Clone, PartialEq, Eq)]
@@ -16,8 +19,8 @@ pub enum AppState {
     LoadLevel,
     Game,
     GameOver,
-    //LevelCompleted,
-    //Editor,
+    LevelCompleted,
+    Editor,
 }
 
 pub struct MenuPlugin;
@@ -27,7 +30,8 @@ impl Plugin for MenuPlugin {
         app.add_system(pause_unpause_game);
         app.add_system(main_menu.in_set(OnUpdate(AppState::MainMenu)));
         app.add_system(pause_menu.in_set(OnUpdate(AppState::PauseMenu)));
-        app.add_system(game_over_menu.in_set(OnUpdate(AppState::GameOver)));
+        app.add_system(results_menu.in_set(OnUpdate(AppState::GameOver)));
+        app.add_system(results_menu.in_set(OnUpdate(AppState::LevelCompleted)));
     }
 }
 
@@ -48,6 +52,8 @@ fn pause_unpause_game(
             }
         }
         AppState::GameOver => {}
+        AppState::LevelCompleted => {}
+        AppState::Editor => {}
     }
 }
 
@@ -66,17 +72,68 @@ fn menu_layout(egui_context: &egui::Context, dlg: impl FnOnce(&mut egui::Ui)) {
 #[derive(PartialEq)]
 enum FocusLabel {
     Start,
+    SelectLevel,
+    Level(usize),
+    Difficulty,
+    Editor,
     #[cfg(not(target_arch = "wasm32"))]
     BackToMainMenu,
     Exit,
 }
 
+#[derive(Default)]
+enum MainMenuPage {
+    #[default]
+    Main,
+    LevelSelect,
+}
+
 fn main_menu(
     mut egui_context: EguiContexts,
     mut state: ResMut<NextState<AppState>>,
+    mut page: Local<MainMenuPage>,
+    mut level_selection: LevelSelection,
+    mut selected_difficulty: ResMut<SelectedDifficulty>,
     #[cfg(not(target_arch = "wasm32"))] mut exit: EventWriter<bevy::app::AppExit>,
 ) {
     menu_layout(egui_context.ctx_mut(), |ui| {
+        if let MainMenuPage::LevelSelect = *page {
+            if ui.kbgp_user_action() == Some(MenuActionForKbgp) {
+                *page = MainMenuPage::Main;
+                ui.kbgp_clear_input();
+                ui.kbgp_set_focus_label(FocusLabel::SelectLevel);
+                return;
+            }
+            let Some(index) = level_selection.index() else {
+                ui.label("Loading...");
+                return;
+            };
+            let mut selected = None;
+            for (entry_idx, entry) in index.levels.iter().enumerate() {
+                if ui
+                    .button(entry.name.as_str())
+                    .kbgp_navigation()
+                    .kbgp_focus_label(FocusLabel::Level(entry_idx))
+                    .clicked()
+                {
+                    selected = Some(entry_idx);
+                }
+            }
+            if let Some(entry_idx) = selected {
+                if level_selection.select(entry_idx) {
+                    *page = MainMenuPage::Main;
+                    state.set(AppState::LoadLevel);
+                    ui.kbgp_clear_input();
+                }
+            }
+            if ui.button("Back").kbgp_navigation().clicked() {
+                *page = MainMenuPage::Main;
+                ui.kbgp_clear_input();
+                ui.kbgp_set_focus_label(FocusLabel::SelectLevel);
+            }
+            return;
+        }
+
         if ui.kbgp_user_action() == Some(MenuActionForKbgp) {
             ui.kbgp_set_focus_label(FocusLabel::Exit);
         }
@@ -87,7 +144,36 @@ fn main_menu(
             .kbgp_focus_label(FocusLabel::Start)
             .clicked()
         {
-            state.set(AppState::LoadLevel);
+            if level_selection.select(0) {
+                state.set(AppState::LoadLevel);
+                ui.kbgp_clear_input();
+            }
+        }
+        if ui
+            .button("Select Level")
+            .kbgp_navigation()
+            .kbgp_focus_label(FocusLabel::SelectLevel)
+            .clicked()
+        {
+            *page = MainMenuPage::LevelSelect;
+            ui.kbgp_clear_input();
+            ui.kbgp_set_focus_label(FocusLabel::Level(0));
+        }
+        if ui
+            .button(format!("Difficulty: {}", selected_difficulty.0.name()))
+            .kbgp_navigation()
+            .kbgp_focus_label(FocusLabel::Difficulty)
+            .clicked()
+        {
+            selected_difficulty.0 = selected_difficulty.0.next();
+        }
+        if ui
+            .button("Level Editor")
+            .kbgp_navigation()
+            .kbgp_focus_label(FocusLabel::Editor)
+            .clicked()
+        {
+            state.set(AppState::Editor);
             ui.kbgp_clear_input();
         }
         #[cfg(not(target_arch = "wasm32"))]
@@ -137,34 +223,46 @@ fn pause_menu(
     });
 }
 
-fn game_over_menu(
+fn results_menu(
     mut egui_context: EguiContexts,
-    mut state: ResMut<NextState<AppState>>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    outcome: Res<LevelOutcome>,
     player_query: Query<&Killable, With<IsPlayer>>,
+    mut level_selection: LevelSelection,
     #[cfg(not(target_arch = "wasm32"))] mut exit: EventWriter<bevy::app::AppExit>,
 ) {
     menu_layout(egui_context.ctx_mut(), |ui| {
-        ui.label(egui::RichText::new("Game Over!").size(24.0).strong());
-        if player_query.iter().any(|killable| killable.killed) {
-            ui.label(
-                egui::RichText::new("You Died...")
-                    .size(24.0)
-                    .strong()
-                    .color(egui::Color32::RED),
-            );
+        let title = if state.0 == AppState::LevelCompleted {
+            "Level Completed!"
         } else {
-            ui.label(
-                egui::RichText::new("You Survived!!!")
-                    .size(24.0)
-                    .strong()
-                    .color(egui::Color32::GREEN),
-            );
+            "Game Over!"
+        };
+        ui.label(egui::RichText::new(title).size(24.0).strong());
+        let (text, color) = match *outcome {
+            LevelOutcome::Win => ("You Won!!!", egui::Color32::GREEN),
+            LevelOutcome::Draw => ("Draw", egui::Color32::YELLOW),
+            LevelOutcome::Loss | LevelOutcome::Undecided => {
+                if player_query.iter().any(|killable| killable.killed) {
+                    ("You Died...", egui::Color32::RED)
+                } else {
+                    ("You Lost...", egui::Color32::RED)
+                }
+            }
+        };
+        ui.label(egui::RichText::new(text).size(24.0).strong().color(color));
+        if let Some(next_entry) = level_selection.next_entry() {
+            if ui.button("Next Level").kbgp_navigation().clicked()
+                && level_selection.select(next_entry)
+            {
+                next_state.set(AppState::LoadLevel);
+            }
         }
         if ui.button("Retry").kbgp_navigation().clicked() {
-            state.set(AppState::LoadLevel);
+            next_state.set(AppState::LoadLevel);
         }
         if ui.button("Main Menu").kbgp_navigation().clicked() {
-            state.set(AppState::MainMenu);
+            next_state.set(AppState::MainMenu);
             ui.kbgp_clear_input();
             ui.kbgp_set_focus_label(FocusLabel::Start);
         }
@@ -0,0 +1,492 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use float_ord::FloatOrd;
+
+use crate::level::{CurrentLevel, Level, LevelBlock, LevelBlockKind};
+use crate::level_reloading::LevelPopulationSet;
+use crate::utils::project_by_normal;
+
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NavGrid>();
+        app.add_system(bake_nav_grid.in_set(LevelPopulationSet));
+    }
+}
+
+/// Size of a grid cell, on both horizontal axes.
+const CELL_SIZE: f32 = 2.0;
+/// The radius of the participants' capsules. Cells closer than that to a wall can't be stood on.
+const AGENT_RADIUS: f32 = 1.0;
+/// Participants climb this much just by walking.
+const STEP_HEIGHT: f32 = 1.0;
+/// Participants need to jump to climb more than [`STEP_HEIGHT`], and can't climb more than this.
+const JUMP_HEIGHT: f32 = 3.0;
+/// Participants don't walk off ledges higher than this.
+const MAX_DROP: f32 = 10.0;
+/// Path searches give up after visiting this many cells.
+const MAX_SEARCHED_CELLS: usize = 5000;
+
+/// The heights participants can stand at over the level, in a grid of cells. Baked from the
+/// level's blocks when the level is loaded.
+#[derive(Resource, Default)]
+pub struct NavGrid {
+    /// The corner of the grid with the lowest X and Z.
+    origin: Vec2,
+    width: usize,
+    depth: usize,
+    /// The floor height of each cell, or `None` if it can't be stood on.
+    floors: Vec<Option<f32>>,
+}
+
+/// The footprint of a block on the horizontal plane, grown by `margin` on all sides.
+fn block_covers(block: &LevelBlock, point: Vec2, margin: f32) -> bool {
+    let offset = Vec3::new(point.x - block.position.x, 0.0, point.y - block.position.z);
+    let local = Quat::from_rotation_y(block.yaw).inverse().mul_vec3(offset);
+    local.x.abs() <= 0.5 * block.size.x + margin && local.z.abs() <= 0.5 * block.size.z + margin
+}
+
+fn block_top(block: &LevelBlock) -> f32 {
+    block.position.y + 0.5 * block.size.y
+}
+
+impl NavGrid {
+    fn bake(blocks: &[LevelBlock]) -> Self {
+        let mut min = Vec2::splat(f32::INFINITY);
+        let mut max = Vec2::splat(f32::NEG_INFINITY);
+        for block in blocks.iter() {
+            let rotation = Quat::from_rotation_y(block.yaw);
+            for (x, z) in [(-0.5, -0.5), (-0.5, 0.5), (0.5, -0.5), (0.5, 0.5)] {
+                let corner = block.position
+                    + rotation.mul_vec3(Vec3::new(x * block.size.x, 0.0, z * block.size.z));
+                min = min.min(Vec2::new(corner.x, corner.z));
+                max = max.max(Vec2::new(corner.x, corner.z));
+            }
+        }
+        if !(min.is_finite() && max.is_finite()) {
+            return Self::default();
+        }
+        let mut grid = Self {
+            origin: min,
+            width: ((max.x - min.x) / CELL_SIZE).ceil() as usize,
+            depth: ((max.y - min.y) / CELL_SIZE).ceil() as usize,
+            floors: Vec::new(),
+        };
+        grid.floors = (0..grid.width * grid.depth)
+            .map(|index| {
+                let center = grid.cell_center(index);
+                let floor = blocks
+                    .iter()
+                    .filter(|block| block.kind != LevelBlockKind::Wall)
+                    .filter(|block| block_covers(block, center, 0.0))
+                    .map(|block| FloatOrd(block_top(block)))
+                    .max()?
+                    .0;
+                // Too close to a wall, or to a block too high to jump on, for the capsule to fit
+                let blocked = blocks.iter().any(|block| {
+                    block_covers(block, center, AGENT_RADIUS)
+                        && (block.kind == LevelBlockKind::Wall
+                            || floor + JUMP_HEIGHT < block_top(block))
+                });
+                (!blocked).then_some(floor)
+            })
+            .collect();
+        grid
+    }
+
+    fn cell_center(&self, index: usize) -> Vec2 {
+        let (i, j) = (index % self.width, index / self.width);
+        self.origin + CELL_SIZE * Vec2::new(i as f32 + 0.5, j as f32 + 0.5)
+    }
+
+    fn cell_coords(&self, position: Vec3) -> Option<(usize, usize)> {
+        let local = (Vec2::new(position.x, position.z) - self.origin) / CELL_SIZE;
+        if local.x < 0.0 || local.y < 0.0 {
+            return None;
+        }
+        let (i, j) = (local.x as usize, local.y as usize);
+        (i < self.width && j < self.depth).then_some((i, j))
+    }
+
+    fn waypoint(&self, index: usize) -> Vec3 {
+        let center = self.cell_center(index);
+        Vec3::new(center.x, self.floors[index].unwrap_or_default(), center.y)
+    }
+
+    /// The height participants stand at in the cell of `position`, if they can stand there.
+    pub fn floor_height(&self, position: Vec3) -> Option<f32> {
+        let (i, j) = self.cell_coords(position)?;
+        self.floors[j * self.width + i]
+    }
+
+    /// The walkable cell closest to `position`, searching a few cells around it.
+    fn nearest_walkable_cell(&self, position: Vec3) -> Option<usize> {
+        let (i, j) = self.cell_coords(position)?;
+        let (i, j) = (i as isize, j as isize);
+        let mut best = None;
+        for dj in -2..=2 {
+            for di in -2..=2 {
+                let (i, j) = (i + di, j + dj);
+                if i < 0 || j < 0 || self.width as isize <= i || self.depth as isize <= j {
+                    continue;
+                }
+                let index = j as usize * self.width + i as usize;
+                if self.floors[index].is_none() {
+                    continue;
+                }
+                let distance = di * di + dj * dj;
+                if best.map_or(true, |(_, best_distance)| distance < best_distance) {
+                    best = Some((index, distance));
+                }
+            }
+        }
+        best.map(|(index, _)| index)
+    }
+
+    fn can_move(from_floor: f32, to_floor: f32) -> bool {
+        to_floor - from_floor <= JUMP_HEIGHT && from_floor - to_floor <= MAX_DROP
+    }
+
+    /// The walkable neighbors of a cell, with the cost of moving to them. Diagonal moves are only
+    /// allowed when both cells next to the diagonal are walkable, so paths don't cut corners.
+    fn neighbors(&self, index: usize) -> impl Iterator<Item = (usize, f32)> + '_ {
+        let (i, j) = ((index % self.width) as isize, (index / self.width) as isize);
+        let floor = self.floors[index];
+        let walkable = move |i: isize, j: isize| -> Option<usize> {
+            if i < 0 || j < 0 || self.width as isize <= i || self.depth as isize <= j {
+                return None;
+            }
+            let neighbor = j as usize * self.width + i as usize;
+            Self::can_move(floor?, self.floors[neighbor]?).then_some(neighbor)
+        };
+        [
+            (-1, 0),
+            (1, 0),
+            (0, -1),
+            (0, 1),
+            (-1, -1),
+            (-1, 1),
+            (1, -1),
+            (1, 1),
+        ]
+        .into_iter()
+        .filter_map(move |(di, dj)| {
+            let neighbor = walkable(i + di, j + dj)?;
+            if di != 0 && dj != 0 {
+                walkable(i + di, j)?;
+                walkable(i, j + dj)?;
+            }
+            let cost = CELL_SIZE * Vec2::new(di as f32, dj as f32).length();
+            Some((neighbor, cost))
+        })
+    }
+
+    /// Waypoints from `from` to `to`, at the height of the floor along the way. `None` when there
+    /// is no known way there.
+    pub fn find_path(&self, from: Vec3, to: Vec3) -> Option<Vec<Vec3>> {
+        let start = self.nearest_walkable_cell(from)?;
+        let goal = self.nearest_walkable_cell(to)?;
+        let goal_center = self.cell_center(goal);
+        let heuristic = |index: usize| self.cell_center(index).distance(goal_center);
+
+        let mut came_from = HashMap::<usize, (usize, f32)>::default();
+        came_from.insert(start, (start, 0.0));
+        let mut open = BinaryHeap::new();
+        open.push((Reverse(FloatOrd(heuristic(start))), start));
+        let mut searched = 0;
+        while let Some((_, current)) = open.pop() {
+            if current == goal {
+                let mut path = vec![current];
+                while let Some((previous, _)) = came_from.get(path.last().unwrap()) {
+                    if *previous == start {
+                        break;
+                    }
+                    path.push(*previous);
+                }
+                path.reverse();
+                return Some(self.simplify(start, &path));
+            }
+            searched += 1;
+            if MAX_SEARCHED_CELLS < searched {
+                return None;
+            }
+            let current_cost = came_from[&current].1;
+            for (neighbor, step_cost) in self.neighbors(current) {
+                let cost = current_cost + step_cost;
+                if came_from
+                    .get(&neighbor)
+                    .map_or(true, |(_, known_cost)| cost < *known_cost)
+                {
+                    came_from.insert(neighbor, (current, cost));
+                    open.push((Reverse(FloatOrd(cost + heuristic(neighbor))), neighbor));
+                }
+            }
+        }
+        None
+    }
+
+    /// Drop the waypoints in the middle of straight, flat runs.
+    fn simplify(&self, start: usize, path: &[usize]) -> Vec<Vec3> {
+        let mut waypoints = Vec::new();
+        let mut previous = self.waypoint(start);
+        for (index, cell) in path.iter().enumerate() {
+            let waypoint = self.waypoint(*cell);
+            if let Some(next) = path.get(index + 1).map(|next| self.waypoint(*next)) {
+                let straight = (waypoint - previous)
+                    .normalize_or_zero()
+                    .abs_diff_eq((next - waypoint).normalize_or_zero(), 0.01);
+                if straight {
+                    previous = waypoint;
+                    continue;
+                }
+            }
+            waypoints.push(waypoint);
+            previous = waypoint;
+        }
+        waypoints
+    }
+
+    /// How far a participant can run from `from` in a straight line along `direction`, up to
+    /// `max_distance`, without hitting a wall or a ledge.
+    pub fn walkable_ahead(&self, from: Vec3, direction: Vec3, max_distance: f32) -> Vec3 {
+        let direction = project_by_normal(direction, Vec3::Y).normalize_or_zero();
+        let Some(mut floor) = self.floor_height(from) else {
+            return from + max_distance * direction;
+        };
+        let mut reached = from;
+        let step = 0.5 * CELL_SIZE;
+        let mut distance = step;
+        while distance <= max_distance {
+            let point = from + distance * direction;
+            let Some(next_floor) = self.floor_height(point) else { break };
+            if !Self::can_move(floor, next_floor) {
+                break;
+            }
+            floor = next_floor;
+            reached = point;
+            distance += step;
+        }
+        reached
+    }
+
+    /// Whether a participant can get from `from` to `to` in a straight line, without hitting a
+    /// wall or a ledge. Being off the walkable floor at `from` (e.g. brushing against a wall)
+    /// does not count.
+    pub fn can_walk_straight(&self, from: Vec3, to: Vec3) -> bool {
+        let vector = project_by_normal(to - from, Vec3::Y);
+        let distance = vector.length();
+        let direction = vector.normalize_or_zero();
+        let mut floor = self.floor_height(from);
+        let step = 0.5 * CELL_SIZE;
+        let mut traveled = step;
+        while traveled < distance {
+            let next_floor = self.floor_height(from + traveled * direction);
+            match (floor, next_floor) {
+                (Some(floor), Some(next_floor)) => {
+                    if !Self::can_move(floor, next_floor) {
+                        return false;
+                    }
+                }
+                (Some(_), None) => return false,
+                (None, _) => {}
+            }
+            floor = next_floor.or(floor);
+            traveled += step;
+        }
+        true
+    }
+}
+
+fn bake_nav_grid(
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+    mut nav_grid: ResMut<NavGrid>,
+) {
+    let Some(level) = levels.get(&current_level.handle) else { return };
+    *nav_grid = NavGrid::bake(&level.blocks);
+}
+
+/// A goal that moved this far from where the path was found to gets a new path.
+const REPATH_DISTANCE: f32 = 2.0;
+/// Paths are also found again this often, in case there was no path before or the opponent got
+/// pushed too far off it.
+const REPATH_INTERVAL: f32 = 1.0;
+/// How close (horizontally) to a waypoint counts as reaching it.
+const WAYPOINT_RADIUS: f32 = 1.5;
+/// How close to a waypoint too high to step on a participant jumps.
+const JUMP_DISTANCE: f32 = 3.0;
+
+/// Keeps the path an opponent is following to its current goal.
+#[derive(Component)]
+pub struct PathFollower {
+    goal: Option<Vec3>,
+    /// The waypoints not reached yet, last one first.
+    waypoints: Vec<Vec3>,
+    repath_timer: Timer,
+}
+
+impl Default for PathFollower {
+    fn default() -> Self {
+        Self {
+            goal: None,
+            waypoints: Vec::new(),
+            repath_timer: Timer::from_seconds(REPATH_INTERVAL, TimerMode::Repeating),
+        }
+    }
+}
+
+pub struct Steering {
+    pub direction: Vec3,
+    pub jump: bool,
+}
+
+impl PathFollower {
+    /// Which way to go from `position` to get to `goal`. Without a known path, goes straight at
+    /// it.
+    pub fn steer(
+        &mut self,
+        nav_grid: &NavGrid,
+        delta: Duration,
+        position: Vec3,
+        goal: Vec3,
+    ) -> Steering {
+        self.pop_reached_waypoints(position);
+        let needs_path = self.repath_timer.tick(delta).just_finished()
+            || self
+                .goal
+                .map_or(true, |path_goal| REPATH_DISTANCE < path_goal.distance(goal))
+            // Pushed away from the path, to where the next waypoint can't be reached directly
+            || self
+                .waypoints
+                .last()
+                .map_or(false, |waypoint| !nav_grid.can_walk_straight(position, *waypoint));
+        if needs_path {
+            self.goal = Some(goal);
+            self.waypoints = nav_grid.find_path(position, goal).unwrap_or_default();
+            self.waypoints.reverse();
+            self.repath_timer.reset();
+            self.pop_reached_waypoints(position);
+        }
+        let Some(waypoint) = self.waypoints.last() else {
+            return Steering {
+                direction: project_by_normal(goal - position, Vec3::Y).normalize_or_zero(),
+                jump: false,
+            };
+        };
+        let vector_to_waypoint = project_by_normal(*waypoint - position, Vec3::Y);
+        let jump = vector_to_waypoint.length() < JUMP_DISTANCE
+            && nav_grid
+                .floor_height(position)
+                .map_or(false, |floor| STEP_HEIGHT < waypoint.y - floor);
+        Steering {
+            direction: vector_to_waypoint.normalize_or_zero(),
+            jump,
+        }
+    }
+
+    fn pop_reached_waypoints(&mut self, position: Vec3) {
+        while let Some(waypoint) = self.waypoints.last() {
+            if project_by_normal(*waypoint - position, Vec3::Y).length() < WAYPOINT_RADIUS {
+                self.waypoints.pop();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(kind: LevelBlockKind, position: Vec3, size: Vec3) -> LevelBlock {
+        LevelBlock {
+            kind,
+            position,
+            size,
+            yaw: 0.0,
+        }
+    }
+
+    /// A flat 40x40 floor at height 0, centered at the origin.
+    fn ground() -> LevelBlock {
+        block(
+            LevelBlockKind::Ground,
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(40.0, 2.0, 40.0),
+        )
+    }
+
+    #[test]
+    fn straight_path_is_simplified() {
+        let grid = NavGrid::bake(&[ground()]);
+        let path = grid
+            .find_path(Vec3::new(-19.0, 0.0, -1.0), Vec3::new(17.0, 0.0, -1.0))
+            .unwrap();
+        assert_eq!(path, vec![Vec3::new(17.0, 0.0, -1.0)]);
+    }
+
+    #[test]
+    fn path_goes_around_wall() {
+        let wall = block(
+            LevelBlockKind::Wall,
+            Vec3::new(0.0, 5.0, -5.0),
+            Vec3::new(2.0, 10.0, 30.0),
+        );
+        let grid = NavGrid::bake(&[ground(), wall]);
+        let path = grid
+            .find_path(Vec3::new(-10.0, 0.0, -10.0), Vec3::new(10.0, 0.0, -10.0))
+            .unwrap();
+        // The wall reaches up to Z=10
+        assert!(path.iter().any(|waypoint| 10.0 < waypoint.z));
+        assert!(path
+            .iter()
+            .all(|waypoint| 2.0 < waypoint.x.abs() || 10.0 < waypoint.z));
+    }
+
+    #[test]
+    fn no_path_into_walled_off_area() {
+        let wall = block(
+            LevelBlockKind::Wall,
+            Vec3::new(0.0, 5.0, 0.0),
+            Vec3::new(2.0, 10.0, 40.0),
+        );
+        let grid = NavGrid::bake(&[ground(), wall]);
+        assert!(grid
+            .find_path(Vec3::new(-10.0, 0.0, 0.0), Vec3::new(10.0, 0.0, 0.0))
+            .is_none());
+    }
+
+    #[test]
+    fn can_jump_on_low_obstacle() {
+        let obstacle = block(
+            LevelBlockKind::Obstacle,
+            Vec3::new(10.0, 1.25, 0.0),
+            Vec3::new(8.0, 2.5, 8.0),
+        );
+        let grid = NavGrid::bake(&[ground(), obstacle]);
+        let path = grid
+            .find_path(Vec3::new(-10.0, 0.0, 0.0), Vec3::new(10.0, 2.5, 0.0))
+            .unwrap();
+        assert_eq!(path.last().unwrap().y, 2.5);
+    }
+
+    #[test]
+    fn cannot_jump_on_high_obstacle() {
+        let obstacle = block(
+            LevelBlockKind::Obstacle,
+            Vec3::new(10.0, 2.5, 0.0),
+            Vec3::new(8.0, 5.0, 8.0),
+        );
+        let grid = NavGrid::bake(&[ground(), obstacle]);
+        assert_eq!(grid.floor_height(Vec3::new(10.0, 5.0, 0.0)), Some(5.0));
+        assert!(grid
+            .find_path(Vec3::new(-10.0, 0.0, 0.0), Vec3::new(10.0, 5.0, 0.0))
+            .is_none());
+    }
+}
@@ -1,5 +1,3 @@
-use std::f32::consts::PI;
-
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 use bevy_tnua::{
@@ -8,12 +6,15 @@ use bevy_tnua::{
 };
 
 use crate::animation::{GltfSceneHandler, HumanAnimationState};
-use crate::bumpin::BumpStatus;
+use crate::bumpin::{BumpConfig, BumpStatus, PreStepVelocity};
 use crate::collision_groups;
 use crate::crosshair::{Aimedatable, Intimidatable};
-use crate::killing::Killable;
+use crate::difficulty::{OpponentDifficulty, SelectedDifficulty};
+use crate::headless::VisualAssets;
+use crate::killing::{Health, Killable};
+use crate::level::{CurrentLevel, DamageRules, Level, OpponentSpawn};
 use crate::level_reloading::{CleanOnLevelReload, LevelPopulationSet};
-use crate::menu::AppState;
+use crate::navigation::PathFollower;
 use crate::opponent_behavior::OpponentBehavior;
 use crate::rifle::{AimElevation, RifleHolder};
 
@@ -21,28 +22,37 @@ pub struct OpponentPlugin;
 
 impl Plugin for OpponentPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system({
-            setup_opponents
-                .in_schedule(OnEnter(AppState::LoadLevel))
-                .in_set(LevelPopulationSet)
-        });
+        app.add_system(setup_opponents.in_set(LevelPopulationSet));
     }
 }
 
-fn setup_opponents(mut commands: Commands, asset_server: Res<AssetServer>) {
-    const NUM_OPPONENTS: usize = 8;
-    for idx in 0..NUM_OPPONENTS {
-        let angle = idx as f32 * 2.0 * PI / NUM_OPPONENTS as f32;
-        let position = Quat::from_rotation_y(angle).mul_vec3(Vec3::X * 20.0) + 2.0 * Vec3::Y;
+fn setup_opponents(
+    mut commands: Commands,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+    selected_difficulty: Res<SelectedDifficulty>,
+    visuals: VisualAssets,
+) {
+    let Some(level) = levels.get(&current_level.handle) else { return };
+    for OpponentSpawn {
+        position,
+        config,
+        difficulty,
+    } in level.opponents.iter()
+    {
+        let difficulty = difficulty
+            .as_ref()
+            .unwrap_or(&selected_difficulty.0)
+            .settings();
         let mut cmd = commands.spawn_empty();
         cmd.insert(CleanOnLevelReload);
         cmd.insert(SceneBundle {
-            scene: asset_server.load("human.glb#Scene0"),
-            transform: Transform::from_translation(position),
+            scene: visuals.load("human.glb#Scene0"),
+            transform: Transform::from_translation(*position),
             ..Default::default()
         });
         cmd.insert(GltfSceneHandler {
-            names_from: asset_server.load("human.glb"),
+            names_from: visuals.load("human.glb"),
         });
 
         cmd.insert(RigidBody::Dynamic);
@@ -56,8 +66,8 @@ fn setup_opponents(mut commands: Commands, asset_server: Res<AssetServer>) {
 
         cmd.insert(TnuaPlatformerBundle::new_with_config(
             TnuaPlatformerConfig {
-                full_speed: 20.0,
-                full_jump_height: 4.0,
+                full_speed: difficulty.speed_factor * config.full_speed,
+                full_jump_height: config.full_jump_height,
                 up: Vec3::Y,
                 forward: -Vec3::Z,
                 float_height: 2.0,
@@ -73,18 +83,25 @@ fn setup_opponents(mut commands: Commands, asset_server: Res<AssetServer>) {
                 free_fall_behavior: TnuaFreeFallBehavior::LikeJumpShorten,
                 tilt_offset_angvel: 0.0,
                 tilt_offset_angacl: 0.0,
-                turning_angvel: 5.0,
+                turning_angvel: difficulty.speed_factor * config.turning_angvel,
             },
         ));
         cmd.insert(TnuaPlatformerAnimatingOutput::default());
         cmd.insert(TnuaAnimatingState::<HumanAnimationState>::default());
 
         cmd.insert(BumpStatus::default());
+        cmd.insert(PreStepVelocity::default());
+        cmd.insert(BumpConfig::default());
         cmd.insert(RifleHolder::NoRifle);
         cmd.insert(AimElevation(0.0));
         cmd.insert(Killable { killed: false });
+        if let DamageRules::Health(rules) = &level.rules.damage {
+            cmd.insert(Health::new(rules.clone()));
+        }
         cmd.insert(Intimidatable);
         cmd.insert(Aimedatable::default());
         cmd.insert(OpponentBehavior::default());
+        cmd.insert(OpponentDifficulty(difficulty));
+        cmd.insert(PathFollower::default());
     }
 }
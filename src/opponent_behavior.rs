@@ -1,24 +1,41 @@
+use std::f32::consts::TAU;
+
 use bevy::prelude::*;
 use bevy_tnua::TnuaPlatformerControls;
 use bevy_turborand::{DelegatedRng, GlobalRng};
 use float_ord::FloatOrd;
 
-use crate::crosshair::Aimedatable;
+use crate::difficulty::{OpponentDifficulty, TargetPreference};
 use crate::killing::Killable;
-use crate::menu::AppState;
+use crate::line_of_sight::LineOfSight;
+use crate::navigation::{NavGrid, PathFollower};
+use crate::opponent_brain::{
+    AddBehaviorCandidate, BehaviorCandidate, OpponentDecisionSet, Perception,
+};
 use crate::player::IsPlayer;
-use crate::rifle::{RifleStatus, ShootCommand};
+use crate::rifle::{RifleHolder, RifleStatus, ShootCommand, ThrowCommand};
 use crate::utils::project_by_normal;
+use crate::weapon::{Weapon, WeaponDefinition};
+use crate::{GameplaySet, ShootingSequenceSet};
 
 pub struct OpponentBehaviorPlugin;
 
 impl Plugin for OpponentBehaviorPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            (decide_what_to_do, process_behavior)
-                .chain()
-                .in_set(OnUpdate(AppState::Game)),
-        );
+        app.add_behavior_candidate(FlingEmptyRifleCandidate);
+        app.add_behavior_candidate(ShootCandidate);
+        app.add_behavior_candidate(ThrowCandidate);
+        app.add_behavior_candidate(FindTargetCandidate);
+        app.add_behavior_candidate(GetRifleCandidate);
+        app.add_behavior_candidate(HandsUpCandidate);
+        app.add_behavior_candidate(PanicCandidate);
+        app.add_system({
+            process_behavior
+                .after(OpponentDecisionSet)
+                .before(ShootingSequenceSet::ShootInitiator)
+                .in_set(GameplaySet)
+                .in_schedule(CoreSchedule::FixedUpdate)
+        });
     }
 }
 
@@ -30,9 +47,20 @@ pub enum OpponentBehavior {
     Shoot {
         rifle: Entity,
     },
+    Throw {
+        rifle: Entity,
+        target: Entity,
+    },
     Panic {
-        run_from: Vec3,
-        run_direction_in_shooter_coord: Vec3,
+        /// The rifle to hide from.
+        threat: Entity,
+        /// Where to hide from it, when there is such a place nearby.
+        cover: Option<Vec3>,
+        /// Whenever it finishes, look for cover again and switch the zig-zag side.
+        reconsider_timer: Timer,
+        /// Which side (`1.0` or `-1.0`) of running straight away from the threat to zig-zag to
+        /// when there is no cover.
+        zigzag_side: f32,
     },
     HandsUp {
         aimed_at_by: Entity,
@@ -44,14 +72,14 @@ pub enum OpponentBehavior {
 }
 
 impl OpponentBehavior {
-    fn wait_before(seconds: f32, followup: OpponentBehavior) -> Self {
+    pub fn wait_before(seconds: f32, followup: OpponentBehavior) -> Self {
         Self::WaitBefore {
             timer: Timer::from_seconds(seconds, TimerMode::Once),
             followup: Some(Box::new(followup)),
         }
     }
 
-    fn wait(seconds: f32) -> Self {
+    pub fn wait(seconds: f32) -> Self {
         Self::WaitBefore {
             timer: Timer::from_seconds(seconds, TimerMode::Once),
             followup: None,
@@ -59,174 +87,450 @@ impl OpponentBehavior {
     }
 }
 
-const MIN_DISTANCE_FOR_SHOOTING: f32 = 25.0;
+pub const MIN_DISTANCE_FOR_SHOOTING: f32 = 25.0;
+/// How far to the side an opponent that can't see any target checks whether it would see one.
+const STRAFE_PROBE_DISTANCE: f32 = 4.0;
+/// How far (in radians) from facing the target an opponent may throw the rifle.
+const THROW_AIM_TOLERANCE: f32 = 0.2;
+/// How far ahead a panicking opponent looks for floor to run on.
+const PANIC_LOOKAHEAD: f32 = 10.0;
+/// How often a panicking opponent looks for cover again.
+const PANIC_RECONSIDER_PERIOD: f32 = 0.6;
+/// Distances from a panicking opponent at which it looks for cover.
+const COVER_SEARCH_RADII: [f32; 3] = [6.0, 12.0, 18.0];
+/// Number of directions a panicking opponent looks for cover in, at each distance.
+const COVER_SEARCH_DIRECTIONS: usize = 12;
+/// How high above the floor a cover must block the view - about the middle of the capsule.
+const COVER_CHECK_HEIGHT: f32 = 2.0;
+/// How close to the cover an opponent stops running.
+const COVER_REACHED_DISTANCE: f32 = 1.5;
+/// How far (in radians) from straight away from the threat an opponent with no cover zig-zags.
+const ZIGZAG_ANGLE: f32 = 0.6;
+/// When a wall leaves less than this to run on, the zig-zag switches sides early.
+const ZIGZAG_MIN_RUN: f32 = 4.0;
 
-fn decide_what_to_do(
-    time: Res<Time>,
-    rifles_query: Query<(Entity, &RifleStatus, &GlobalTransform)>,
-    aimmedatables_query: Query<(&Aimedatable, &GlobalTransform)>,
-    transforms_query: Query<&GlobalTransform>,
-    mut opponents_query: Query<(Entity, &mut OpponentBehavior, &GlobalTransform)>,
-    mut rng: ResMut<GlobalRng>,
-    players_query: Query<&IsPlayer>,
-) {
-    let Ok((rifle, rifle_status, rifle_transform)) = rifles_query.get_single() else { return };
-    let rifle_position = rifle_transform.translation();
-    for (entity, mut behavior, transform) in opponents_query.iter_mut() {
-        if let OpponentBehavior::WaitBefore { timer, followup } = behavior.as_mut() {
-            if timer.tick(time.delta()).finished() {
-                if let Some(followup) = followup.take() {
-                    *behavior = *followup;
-                    continue;
-                } // else branch follows through to select a behavior
-            } else {
-                continue;
-            }
+/// Pulling the trigger on an empty rifle flings it, freeing the hands for a loaded one.
+struct FlingEmptyRifleCandidate;
+
+impl BehaviorCandidate for FlingEmptyRifleCandidate {
+    fn score(&self, perception: &Perception, _current: &OpponentBehavior) -> Option<f32> {
+        perception.rifle?;
+        (perception.rounds_left == 0).then_some(100.0)
+    }
+
+    fn is_current(&self, _behavior: &OpponentBehavior) -> bool {
+        // Each pull of the trigger is decided anew
+        false
+    }
+
+    fn start(
+        &self,
+        perception: &Perception,
+        _current: &OpponentBehavior,
+        _rng: &mut GlobalRng,
+    ) -> OpponentBehavior {
+        OpponentBehavior::Shoot {
+            rifle: perception.rifle.unwrap(),
         }
-        if let RifleStatus::Equiped(holder) = rifle_status {
-            #[allow(clippy::collapsible_else_if)]
-            if *holder == entity {
-                let position = transform.translation();
-                if aimmedatables_query
-                    .iter()
-                    .any(|(aimedatable, aimedatable_transform)| {
-                        aimedatable.aimed_at_by == Some(*holder) && {
-                            let vector_to_aimedatable = project_by_normal(
-                                aimedatable_transform.translation() - position,
-                                Vec3::Y,
-                            );
-                            MIN_DISTANCE_FOR_SHOOTING < vector_to_aimedatable.length()
-                        }
-                    })
-                {
-                    *behavior =
-                        OpponentBehavior::wait_before(1.0, OpponentBehavior::Shoot { rifle });
-                } else if !matches!(*behavior, OpponentBehavior::FindTarget) {
-                    *behavior = OpponentBehavior::wait_before(1.0, OpponentBehavior::FindTarget);
-                }
-            } else {
-                if matches!(*behavior, OpponentBehavior::Shoot { .. }) {
-                    *behavior = OpponentBehavior::wait_before(1.0, OpponentBehavior::GetRifle);
-                } else if let Some(aimed_at_by) =
-                    aimmedatables_query
-                        .get(entity)
-                        .ok()
-                        .and_then(|(aimedatable, _)| {
-                            let aimed_at_by = aimedatable.aimed_at_by?;
-                            if players_query.contains(aimed_at_by) {
-                                Some(aimed_at_by)
-                            } else {
-                                let aimed_at_by_transform =
-                                    transforms_query.get(aimed_at_by).ok()?;
-                                let vector_to_aimed_at_by = project_by_normal(
-                                    aimed_at_by_transform.translation() - transform.translation(),
-                                    Vec3::Y,
-                                );
-                                if MIN_DISTANCE_FOR_SHOOTING <= vector_to_aimed_at_by.length() {
-                                    Some(aimed_at_by)
-                                } else {
-                                    None
-                                }
-                            }
-                        })
-                {
-                    *behavior = OpponentBehavior::HandsUp { aimed_at_by };
-                } else if let OpponentBehavior::Panic {
-                    run_from,
-                    run_direction_in_shooter_coord: _,
-                } = behavior.as_mut()
-                {
-                    *run_from = rifle_position;
-                } else {
-                    if matches!(*behavior, OpponentBehavior::HandsUp { .. }) {
-                        *behavior = OpponentBehavior::wait(1.0);
-                    } else {
-                        *behavior = OpponentBehavior::Panic {
-                            run_from: rifle_position,
-                            run_direction_in_shooter_coord: {
-                                let mut direction =
-                                    Quat::from_rotation_y(0.5 * rng.f32()).mul_vec3(Vec3::X);
-                                if rng.bool() {
-                                    direction.x *= -1.0;
-                                }
-                                direction
-                            },
-                        };
-                    }
-                }
-            }
-        } else if matches!(*behavior, OpponentBehavior::Shoot { .. }) {
-            *behavior = OpponentBehavior::wait_before(1.0, OpponentBehavior::GetRifle);
+    }
+}
+
+struct ShootCandidate;
+
+impl BehaviorCandidate for ShootCandidate {
+    fn score(&self, perception: &Perception, _current: &OpponentBehavior) -> Option<f32> {
+        perception.rifle?;
+        let target = perception.aiming_at?;
+        (MIN_DISTANCE_FOR_SHOOTING < target.distance).then_some(90.0)
+    }
+
+    fn is_current(&self, _behavior: &OpponentBehavior) -> bool {
+        // Each shot is decided anew, with its own hesitation
+        false
+    }
+
+    fn start(
+        &self,
+        perception: &Perception,
+        _current: &OpponentBehavior,
+        _rng: &mut GlobalRng,
+    ) -> OpponentBehavior {
+        // Follow-up shots come faster than the first one
+        let hesitation = if perception.magazine_partly_used {
+            perception.difficulty.follow_up_delay
+        } else {
+            perception.difficulty.reaction_delay
+        };
+        OpponentBehavior::wait_before(
+            hesitation,
+            OpponentBehavior::Shoot {
+                rifle: perception.rifle.unwrap(),
+            },
+        )
+    }
+}
+
+struct ThrowCandidate;
+
+impl BehaviorCandidate for ThrowCandidate {
+    fn score(&self, perception: &Perception, _current: &OpponentBehavior) -> Option<f32> {
+        perception.rifle?;
+        perception.throw_target?;
+        Some(80.0)
+    }
+
+    fn is_current(&self, behavior: &OpponentBehavior) -> bool {
+        matches!(behavior, OpponentBehavior::Throw { .. })
+    }
+
+    fn start(
+        &self,
+        perception: &Perception,
+        _current: &OpponentBehavior,
+        _rng: &mut GlobalRng,
+    ) -> OpponentBehavior {
+        OpponentBehavior::Throw {
+            rifle: perception.rifle.unwrap(),
+            target: perception.throw_target.unwrap(),
+        }
+    }
+}
+
+/// What an opponent with a rifle does when there is nothing better to do.
+struct FindTargetCandidate;
+
+impl BehaviorCandidate for FindTargetCandidate {
+    fn score(&self, perception: &Perception, _current: &OpponentBehavior) -> Option<f32> {
+        perception.rifle?;
+        Some(10.0)
+    }
+
+    fn is_current(&self, behavior: &OpponentBehavior) -> bool {
+        matches!(behavior, OpponentBehavior::FindTarget)
+    }
+
+    fn start(
+        &self,
+        perception: &Perception,
+        _current: &OpponentBehavior,
+        _rng: &mut GlobalRng,
+    ) -> OpponentBehavior {
+        OpponentBehavior::wait_before(
+            perception.difficulty.reaction_delay,
+            OpponentBehavior::FindTarget,
+        )
+    }
+}
+
+struct GetRifleCandidate;
+
+impl BehaviorCandidate for GetRifleCandidate {
+    fn score(&self, perception: &Perception, current: &OpponentBehavior) -> Option<f32> {
+        if perception.rifle.is_some() {
+            None
+        } else if matches!(
+            current,
+            OpponentBehavior::Shoot { .. } | OpponentBehavior::Throw { .. }
+        ) {
+            // Just lost the rifle - recover from that before anything else
+            Some(95.0)
+        } else if perception.free_rifle_available {
+            Some(50.0)
+        } else {
+            // Not even rifles to run away from
+            Some(1.0)
+        }
+    }
+
+    fn is_current(&self, behavior: &OpponentBehavior) -> bool {
+        matches!(behavior, OpponentBehavior::GetRifle)
+    }
+
+    fn start(
+        &self,
+        perception: &Perception,
+        current: &OpponentBehavior,
+        _rng: &mut GlobalRng,
+    ) -> OpponentBehavior {
+        if matches!(
+            current,
+            OpponentBehavior::Shoot { .. } | OpponentBehavior::Throw { .. }
+        ) {
+            OpponentBehavior::wait_before(
+                perception.difficulty.reaction_delay,
+                OpponentBehavior::GetRifle,
+            )
         } else {
-            *behavior = OpponentBehavior::GetRifle;
+            OpponentBehavior::GetRifle
+        }
+    }
+}
+
+struct HandsUpCandidate;
+
+impl HandsUpCandidate {
+    fn aimed_at_by(perception: &Perception) -> Option<Entity> {
+        if perception.rifle.is_some() {
+            return None;
         }
+        let aimer = perception.aimed_at_by?;
+        (aimer.is_player || MIN_DISTANCE_FOR_SHOOTING <= aimer.distance).then_some(aimer.entity)
     }
 }
 
+impl BehaviorCandidate for HandsUpCandidate {
+    fn score(&self, perception: &Perception, _current: &OpponentBehavior) -> Option<f32> {
+        Self::aimed_at_by(perception)?;
+        Some(70.0)
+    }
+
+    fn is_current(&self, behavior: &OpponentBehavior) -> bool {
+        matches!(behavior, OpponentBehavior::HandsUp { .. })
+    }
+
+    fn start(
+        &self,
+        perception: &Perception,
+        _current: &OpponentBehavior,
+        _rng: &mut GlobalRng,
+    ) -> OpponentBehavior {
+        OpponentBehavior::HandsUp {
+            aimed_at_by: Self::aimed_at_by(perception).unwrap(),
+        }
+    }
+
+    fn update(&self, perception: &Perception, behavior: &mut OpponentBehavior) {
+        if let OpponentBehavior::HandsUp { aimed_at_by } = behavior {
+            *aimed_at_by = Self::aimed_at_by(perception).unwrap();
+        }
+    }
+}
+
+/// All the rifles are taken - hide from the closest one.
+struct PanicCandidate;
+
+impl BehaviorCandidate for PanicCandidate {
+    fn score(&self, perception: &Perception, _current: &OpponentBehavior) -> Option<f32> {
+        if perception.rifle.is_some() || perception.free_rifle_available {
+            return None;
+        }
+        perception.closest_rifle?;
+        Some(40.0)
+    }
+
+    fn is_current(&self, behavior: &OpponentBehavior) -> bool {
+        matches!(behavior, OpponentBehavior::Panic { .. })
+    }
+
+    fn start(
+        &self,
+        perception: &Perception,
+        current: &OpponentBehavior,
+        rng: &mut GlobalRng,
+    ) -> OpponentBehavior {
+        if matches!(current, OpponentBehavior::HandsUp { .. }) {
+            return OpponentBehavior::wait(perception.difficulty.reaction_delay);
+        }
+        let mut reconsider_timer =
+            Timer::from_seconds(PANIC_RECONSIDER_PERIOD, TimerMode::Repeating);
+        // Look for cover right away
+        reconsider_timer.set_elapsed(reconsider_timer.duration());
+        OpponentBehavior::Panic {
+            threat: perception.closest_rifle.unwrap(),
+            cover: None,
+            reconsider_timer,
+            zigzag_side: if rng.bool() { 1.0 } else { -1.0 },
+        }
+    }
+
+    fn update(&self, perception: &Perception, behavior: &mut OpponentBehavior) {
+        if let OpponentBehavior::Panic { threat, .. } = behavior {
+            *threat = perception.closest_rifle.unwrap();
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn process_behavior(
-    rifles_query: Query<&GlobalTransform, With<RifleStatus>>,
+    fixed_time: Res<FixedTime>,
+    rifles_query: Query<(&RifleStatus, &GlobalTransform)>,
+    weapons_query: Query<&Weapon>,
+    weapon_definitions: Res<Assets<WeaponDefinition>>,
     mut opponents_query: Query<(
         Entity,
-        &OpponentBehavior,
+        &mut OpponentBehavior,
         &GlobalTransform,
+        &RifleHolder,
         &mut TnuaPlatformerControls,
+        &mut PathFollower,
+        Option<&OpponentDifficulty>,
     )>,
-    killables_query: Query<(Entity, &Killable, &GlobalTransform)>,
+    killables_query: Query<(Entity, &Killable, &GlobalTransform, Option<&IsPlayer>)>,
     transform_query: Query<&GlobalTransform>,
+    line_of_sight: LineOfSight,
+    nav_grid: Res<NavGrid>,
+    mut rng: ResMut<GlobalRng>,
     mut shoot_commands_writer: EventWriter<ShootCommand>,
+    mut throw_commands_writer: EventWriter<ThrowCommand>,
 ) {
-    let Ok(rifle_transform) = rifles_query.get_single() else { return };
-    let rifle_position = rifle_transform.translation();
-    for (entity, behavior, transform, mut controls) in opponents_query.iter_mut() {
-        match behavior {
+    for (
+        entity,
+        mut behavior,
+        transform,
+        rifle_holder,
+        mut controls,
+        mut path_follower,
+        difficulty,
+    ) in opponents_query.iter_mut()
+    {
+        let difficulty =
+            difficulty.map_or_else(Default::default, |difficulty| difficulty.0.clone());
+        controls.jump = None;
+        match behavior.as_mut() {
             OpponentBehavior::GetRifle => {
-                let direction_to_rifle =
-                    project_by_normal(rifle_position - transform.translation(), Vec3::Y)
-                        .normalize_or_zero();
-                controls.desired_forward = direction_to_rifle;
-                controls.desired_velocity = direction_to_rifle;
+                let closest_rifle = rifles_query
+                    .iter()
+                    .filter(|(rifle_status, _)| !matches!(rifle_status, RifleStatus::Equiped(_)))
+                    .map(|(_, rifle_transform)| rifle_transform.translation())
+                    .min_by_key(|rifle_position| {
+                        FloatOrd(
+                            project_by_normal(*rifle_position - transform.translation(), Vec3::Y)
+                                .length_squared(),
+                        )
+                    });
+                if let Some(rifle_position) = closest_rifle {
+                    let steering = path_follower.steer(
+                        &nav_grid,
+                        fixed_time.period,
+                        transform.translation(),
+                        rifle_position,
+                    );
+                    controls.desired_forward = steering.direction;
+                    controls.desired_velocity = steering.direction;
+                    controls.jump = steering.jump.then_some(1.0);
+                } else {
+                    controls.desired_forward = Vec3::ZERO;
+                    controls.desired_velocity = Vec3::ZERO;
+                }
             }
             OpponentBehavior::FindTarget => {
-                // Yes, hard-coding the rifle position is bad. It's a game jam, I don't have time
-                // to do it properly.
-                let rifle_position = transform.transform_point(Vec3::new(0.65, 0.0, 0.0));
+                let grip_anchor = match rifle_holder {
+                    RifleHolder::NoRifle => None,
+                    RifleHolder::HasRifle(rifle) => weapons_query
+                        .get(*rifle)
+                        .ok()
+                        .and_then(|weapon| weapon_definitions.get(&weapon.0))
+                        .map(|definition| definition.grip_anchor),
+                };
+                let rifle_position = transform.transform_point(grip_anchor.unwrap_or_default());
                 let current_direction = transform.forward();
                 controls.desired_velocity = Vec3::ZERO;
-                let chosen_killable_direction = {
-                    killables_query
-                        .iter()
-                        .filter_map(|(killables_entity, killable, killable_transform)| {
-                            if killables_entity == entity || killable.killed {
-                                None
-                            } else {
-                                let vector_to_killable = project_by_normal(
-                                    killable_transform.translation() - rifle_position,
-                                    Vec3::Y,
-                                );
-                                if vector_to_killable.length() < MIN_DISTANCE_FOR_SHOOTING {
-                                    // To close, don't kill that one
-                                    None
-                                } else {
-                                    let direction_to_killable =
-                                        vector_to_killable.normalize_or_zero();
-                                    Some(direction_to_killable)
-                                }
+                let candidates = killables_query.iter().filter_map(
+                    |(killables_entity, killable, killable_transform, is_player)| {
+                        if killables_entity == entity || killable.killed {
+                            return None;
+                        }
+                        let killable_position = killable_transform.translation();
+                        let vector_to_killable =
+                            project_by_normal(killable_position - rifle_position, Vec3::Y);
+                        if vector_to_killable.length() < MIN_DISTANCE_FOR_SHOOTING {
+                            // To close, don't kill that one
+                            return None;
+                        }
+                        let visible = line_of_sight.can_see(
+                            entity,
+                            rifle_position,
+                            killables_entity,
+                            killable_position,
+                        );
+                        Some((
+                            killables_entity,
+                            killable_position,
+                            vector_to_killable,
+                            is_player.is_some(),
+                            visible,
+                        ))
+                    },
+                );
+                let chosen_killable =
+                    candidates.min_by_key(|(_, _, vector_to_killable, is_player, visible)| {
+                        let turn = current_direction.angle_between(*vector_to_killable);
+                        let preference = match difficulty.target_preference {
+                            TargetPreference::Facing => (false, FloatOrd(turn)),
+                            TargetPreference::Closest => {
+                                (false, FloatOrd(vector_to_killable.length()))
                             }
-                        })
-                        .min_by_key(|direction_to_killable| {
-                            FloatOrd(current_direction.angle_between(*direction_to_killable))
-                        })
-                };
-                if let Some(direction_to_killable) = chosen_killable_direction {
+                            TargetPreference::Player => (!is_player, FloatOrd(turn)),
+                        };
+                        (!visible, preference)
+                    });
+                if let Some((killable, killable_position, vector_to_killable, _, visible)) =
+                    chosen_killable
+                {
+                    let direction_to_killable = vector_to_killable.normalize_or_zero();
                     controls.desired_forward = direction_to_killable;
+                    if !visible {
+                        // Nobody to shoot at from here - sidestep to the side the target can be
+                        // seen from, if any.
+                        let sideways = direction_to_killable.cross(Vec3::Y);
+                        let can_see_from = |offset: Vec3| {
+                            line_of_sight.can_see(
+                                entity,
+                                rifle_position + offset,
+                                killable,
+                                killable_position,
+                            )
+                        };
+                        let strafe_direction = if !can_see_from(STRAFE_PROBE_DISTANCE * sideways)
+                            && can_see_from(-STRAFE_PROBE_DISTANCE * sideways)
+                        {
+                            -sideways
+                        } else {
+                            sideways
+                        };
+                        controls.desired_velocity = strafe_direction;
+                    }
                 }
             }
             OpponentBehavior::Shoot { rifle } => {
+                // The rifle may have been knocked loose while waiting to shoot
+                if !matches!(rifle_holder, RifleHolder::HasRifle(held) if *held == *rifle) {
+                    continue;
+                }
+                let aim_deviation = if 0.0 < difficulty.aim_error {
+                    // Anywhere within a cone around the rifle's forward (its -Z)
+                    let deviation_direction = TAU * rng.f32();
+                    Quat::from_axis_angle(
+                        Vec3::new(deviation_direction.cos(), deviation_direction.sin(), 0.0),
+                        difficulty.aim_error * rng.f32(),
+                    )
+                } else {
+                    Quat::IDENTITY
+                };
                 shoot_commands_writer.send(ShootCommand {
                     shooter: entity,
                     rifle: *rifle,
+                    aim_deviation,
                 });
             }
+            OpponentBehavior::Throw { rifle, target } => {
+                controls.desired_velocity = Vec3::ZERO;
+                let Ok(target_transform) = transform_query.get(*target) else {
+                    continue;
+                };
+                let direction_to_target = project_by_normal(
+                    target_transform.translation() - transform.translation(),
+                    Vec3::Y,
+                )
+                .normalize_or_zero();
+                controls.desired_forward = direction_to_target;
+                if transform.forward().angle_between(direction_to_target) < THROW_AIM_TOLERANCE {
+                    throw_commands_writer.send(ThrowCommand {
+                        thrower: entity,
+                        rifle: *rifle,
+                    });
+                }
+            }
             OpponentBehavior::HandsUp { aimed_at_by } => {
                 controls.desired_velocity = Vec3::ZERO;
                 controls.desired_forward = match transform_query.get(*aimed_at_by) {
@@ -239,18 +543,57 @@ fn process_behavior(
                 };
             }
             OpponentBehavior::Panic {
-                run_from,
-                run_direction_in_shooter_coord,
+                threat,
+                cover,
+                reconsider_timer,
+                zigzag_side,
             } => {
-                let direction_from_danger =
-                    project_by_normal(transform.translation() - *run_from, Vec3::Y)
-                        .normalize_or_zero();
-                let transform_from_danger =
-                    Transform::default().looking_to(direction_from_danger, Vec3::Y);
-                let panic_direction =
-                    transform_from_danger.transform_point(*run_direction_in_shooter_coord);
-                controls.desired_velocity = panic_direction;
-                controls.desired_forward = panic_direction;
+                controls.desired_velocity = Vec3::ZERO;
+                let Ok((threat_status, threat_transform)) = rifles_query.get(*threat) else {
+                    continue;
+                };
+                let position = transform.translation();
+                let threat_position = threat_transform.translation();
+                // The holder's own capsule is in the way of rays cast from the rifle
+                let viewer = match threat_status {
+                    RifleStatus::Equiped(holder) => *holder,
+                    _ => *threat,
+                };
+                if reconsider_timer.tick(fixed_time.period).just_finished() {
+                    *cover = find_cover(
+                        entity,
+                        position,
+                        viewer,
+                        threat_position,
+                        &nav_grid,
+                        &line_of_sight,
+                    );
+                    *zigzag_side *= -1.0;
+                }
+                let vector_from_threat = project_by_normal(position - threat_position, Vec3::Y);
+                let run_to = if let Some(cover) = cover {
+                    if project_by_normal(*cover - position, Vec3::Y).length()
+                        < COVER_REACHED_DISTANCE
+                    {
+                        // Stay in cover, keeping an eye on the threat
+                        controls.desired_forward = -vector_from_threat.normalize_or_zero();
+                        continue;
+                    }
+                    *cover
+                } else {
+                    let zigzag_direction = Quat::from_rotation_y(*zigzag_side * ZIGZAG_ANGLE)
+                        .mul_vec3(vector_from_threat.normalize_or_zero());
+                    let run_to =
+                        nav_grid.walkable_ahead(position, zigzag_direction, PANIC_LOOKAHEAD);
+                    if project_by_normal(run_to - position, Vec3::Y).length() < ZIGZAG_MIN_RUN {
+                        *zigzag_side *= -1.0;
+                    }
+                    run_to
+                };
+                let steering = path_follower.steer(&nav_grid, fixed_time.period, position, run_to);
+                controls.desired_velocity = steering.direction;
+                controls.desired_forward = steering.direction;
+                controls.jump = steering.jump.then_some(1.0);
             }
             OpponentBehavior::WaitBefore { .. } => {
                 controls.desired_velocity = Vec3::ZERO;
@@ -259,3 +602,47 @@ fn process_behavior(
         }
     }
 }
+
+/// The best place around `position` for `entity` to hide from `viewer`, who holds a rifle at
+/// `threat_position`. Closer places are better, and so are places further from the threat.
+fn find_cover(
+    entity: Entity,
+    position: Vec3,
+    viewer: Entity,
+    threat_position: Vec3,
+    nav_grid: &NavGrid,
+    line_of_sight: &LineOfSight,
+) -> Option<Vec3> {
+    let distance_from_threat = project_by_normal(position - threat_position, Vec3::Y).length();
+    let around = COVER_SEARCH_RADII.into_iter().flat_map(|radius| {
+        (0..COVER_SEARCH_DIRECTIONS).map(move |i| {
+            let angle = TAU * i as f32 / COVER_SEARCH_DIRECTIONS as f32;
+            position + radius * Vec3::new(angle.cos(), 0.0, angle.sin())
+        })
+    });
+    // Staying put is also an option, if the opponent is already in cover
+    let mut candidates = std::iter::once(position)
+        .chain(around)
+        .filter_map(|candidate| {
+            let candidate = Vec3::new(candidate.x, nav_grid.floor_height(candidate)?, candidate.z);
+            let candidate_distance_from_threat =
+                project_by_normal(candidate - threat_position, Vec3::Y).length();
+            // Running towards the threat is no way to hide from it
+            if candidate_distance_from_threat < distance_from_threat {
+                return None;
+            }
+            let check_point = candidate + COVER_CHECK_HEIGHT * Vec3::Y;
+            if !line_of_sight.is_hidden(viewer, threat_position, entity, check_point) {
+                return None;
+            }
+            let travel = project_by_normal(candidate - position, Vec3::Y).length();
+            Some((candidate, travel - 0.5 * candidate_distance_from_threat))
+        })
+        .collect::<Vec<_>>();
+    candidates.sort_by_key(|(_, score)| FloatOrd(*score));
+    // Only search for a path to the best candidates, until one is found
+    candidates
+        .into_iter()
+        .map(|(candidate, _)| candidate)
+        .find(|candidate| nav_grid.find_path(position, *candidate).is_some())
+}
@@ -0,0 +1,249 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_turborand::GlobalRng;
+use float_ord::FloatOrd;
+
+use crate::crosshair::Aimedatable;
+use crate::difficulty::{DifficultySettings, OpponentDifficulty};
+use crate::killing::Killable;
+use crate::opponent_behavior::{OpponentBehavior, MIN_DISTANCE_FOR_SHOOTING};
+use crate::player::IsPlayer;
+use crate::rifle::{rounds_left, Magazine, RifleHolder, RifleStatus};
+use crate::utils::project_by_normal;
+use crate::{GameplaySet, ShootingSequenceSet};
+
+/// Picks the [`OpponentBehavior`] of each opponent by scoring the [`BehaviorCandidate`]s
+/// registered in the [`OpponentBrain`].
+pub struct OpponentBrainPlugin;
+
+impl Plugin for OpponentBrainPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<OpponentBrain>();
+        app.add_system({
+            decide_what_to_do
+                .in_set(OpponentDecisionSet)
+                .before(ShootingSequenceSet::ShootInitiator)
+                .in_set(GameplaySet)
+                .in_schedule(CoreSchedule::FixedUpdate)
+        });
+    }
+}
+
+/// Systems that carry out the [`OpponentBehavior`] should run after this set.
+#[derive(SystemSet, Clone, PartialEq, Eq, Debug, Hash)]
+pub struct OpponentDecisionSet;
+
+/// Added to the score of the behavior the opponent is already doing, so that it does not flip
+/// back and forth between candidates with close scores.
+const HYSTERESIS: f32 = 5.0;
+
+/// Another participant the opponent is aware of.
+#[derive(Debug, Clone, Copy)]
+pub struct Sighting {
+    pub entity: Entity,
+    /// Horizontal distance from the opponent.
+    pub distance: f32,
+    pub is_player: bool,
+}
+
+/// What an opponent knows about the world when it decides what to do.
+#[derive(Debug)]
+pub struct Perception {
+    /// The opponent's own difficulty settings.
+    pub difficulty: DifficultySettings,
+    /// The rifle the opponent is holding.
+    pub rifle: Option<Entity>,
+    /// Rounds left in the held rifle.
+    pub rounds_left: usize,
+    /// Whether the held rifle's magazine was already fired from.
+    pub magazine_partly_used: bool,
+    /// Whoever the opponent's rifle is aimed at.
+    pub aiming_at: Option<Sighting>,
+    /// Whoever is aiming their rifle at the opponent.
+    pub aimed_at_by: Option<Sighting>,
+    /// When everyone is too close to shoot, the closest one to throw the rifle at.
+    pub throw_target: Option<Entity>,
+    /// Whether there is a rifle nobody is holding.
+    pub free_rifle_available: bool,
+    /// The closest rifle, held or not.
+    pub closest_rifle: Option<Entity>,
+}
+
+/// A behavior the [`OpponentBrain`] can choose.
+pub trait BehaviorCandidate: Send + Sync + 'static {
+    /// How much the opponent wants to do this, or `None` if it can't.
+    fn score(&self, perception: &Perception, current: &OpponentBehavior) -> Option<f32>;
+
+    /// Whether the opponent's current behavior is this candidate.
+    fn is_current(&self, behavior: &OpponentBehavior) -> bool;
+
+    /// The behavior to switch to when this candidate wins over the current behavior.
+    fn start(
+        &self,
+        perception: &Perception,
+        current: &OpponentBehavior,
+        rng: &mut GlobalRng,
+    ) -> OpponentBehavior;
+
+    /// Called instead of [`start`](Self::start) when the current behavior wins again.
+    fn update(&self, _perception: &Perception, _behavior: &mut OpponentBehavior) {}
+}
+
+#[derive(Resource, Default)]
+pub struct OpponentBrain {
+    candidates: Vec<Box<dyn BehaviorCandidate>>,
+}
+
+impl OpponentBrain {
+    pub fn add_candidate(&mut self, candidate: impl BehaviorCandidate) {
+        self.candidates.push(Box::new(candidate));
+    }
+}
+
+pub trait AddBehaviorCandidate {
+    fn add_behavior_candidate(&mut self, candidate: impl BehaviorCandidate) -> &mut Self;
+}
+
+impl AddBehaviorCandidate for App {
+    fn add_behavior_candidate(&mut self, candidate: impl BehaviorCandidate) -> &mut Self {
+        self.init_resource::<OpponentBrain>();
+        self.world
+            .resource_mut::<OpponentBrain>()
+            .add_candidate(candidate);
+        self
+    }
+}
+
+#[derive(SystemParam)]
+struct Perceiver<'w, 's> {
+    rifles_query: Query<'w, 's, (Entity, &'static RifleStatus, &'static GlobalTransform)>,
+    magazines_query: Query<'w, 's, &'static Magazine>,
+    killables_query: Query<'w, 's, (Entity, &'static Killable, &'static GlobalTransform)>,
+    aimedatables_query: Query<'w, 's, (Entity, &'static Aimedatable)>,
+    transforms_query: Query<'w, 's, &'static GlobalTransform>,
+    players_query: Query<'w, 's, &'static IsPlayer>,
+    difficulties_query: Query<'w, 's, &'static OpponentDifficulty>,
+}
+
+impl Perceiver<'_, '_> {
+    fn perceive(&self, entity: Entity, position: Vec3, rifle_holder: &RifleHolder) -> Perception {
+        let rifle = match rifle_holder {
+            RifleHolder::NoRifle => None,
+            RifleHolder::HasRifle(rifle) => Some(*rifle),
+        };
+        let magazine = rifle.and_then(|rifle| self.magazines_query.get(rifle).ok());
+        let aiming_at = self
+            .aimedatables_query
+            .iter()
+            .find(|(_, aimedatable)| aimedatable.aimed_at_by == Some(entity))
+            .and_then(|(target, _)| self.sighting(position, target));
+        let aimed_at_by = self
+            .aimedatables_query
+            .get(entity)
+            .ok()
+            .and_then(|(_, aimedatable)| aimedatable.aimed_at_by)
+            .and_then(|aimer| self.sighting(position, aimer));
+        Perception {
+            difficulty: self
+                .difficulties_query
+                .get(entity)
+                .map_or_else(|_| Default::default(), |difficulty| difficulty.0.clone()),
+            rifle,
+            rounds_left: rounds_left(magazine),
+            magazine_partly_used: magazine
+                .map_or(false, |magazine| magazine.rounds < magazine.capacity),
+            aiming_at,
+            aimed_at_by,
+            throw_target: self.throw_target(entity, position),
+            free_rifle_available: self
+                .rifles_query
+                .iter()
+                .any(|(_, rifle_status, _)| !matches!(rifle_status, RifleStatus::Equiped(_))),
+            closest_rifle: self
+                .rifles_query
+                .iter()
+                .min_by_key(|(_, _, rifle_transform)| {
+                    FloatOrd(rifle_transform.translation().distance_squared(position))
+                })
+                .map(|(rifle, _, _)| rifle),
+        }
+    }
+
+    fn sighting(&self, position: Vec3, other: Entity) -> Option<Sighting> {
+        let other_transform = self.transforms_query.get(other).ok()?;
+        Some(Sighting {
+            entity: other,
+            distance: project_by_normal(other_transform.translation() - position, Vec3::Y).length(),
+            is_player: self.players_query.contains(other),
+        })
+    }
+
+    fn throw_target(&self, entity: Entity, position: Vec3) -> Option<Entity> {
+        let distances_to_others =
+            self.killables_query
+                .iter()
+                .filter_map(|(other, killable, other_transform)| {
+                    if other == entity || killable.killed {
+                        None
+                    } else {
+                        let vector_to_other =
+                            project_by_normal(other_transform.translation() - position, Vec3::Y);
+                        Some((other, vector_to_other.length()))
+                    }
+                });
+        if distances_to_others
+            .clone()
+            .all(|(_, distance)| distance < MIN_DISTANCE_FOR_SHOOTING)
+        {
+            distances_to_others
+                .min_by_key(|(_, distance)| FloatOrd(*distance))
+                .map(|(other, _)| other)
+        } else {
+            None
+        }
+    }
+}
+
+fn decide_what_to_do(
+    fixed_time: Res<FixedTime>,
+    brain: Res<OpponentBrain>,
+    perceiver: Perceiver,
+    mut opponents_query: Query<(
+        Entity,
+        &mut OpponentBehavior,
+        &GlobalTransform,
+        &RifleHolder,
+    )>,
+    mut rng: ResMut<GlobalRng>,
+) {
+    for (entity, mut behavior, transform, rifle_holder) in opponents_query.iter_mut() {
+        if let OpponentBehavior::WaitBefore { timer, followup } = behavior.as_mut() {
+            if timer.tick(fixed_time.period).finished() {
+                if let Some(followup) = followup.take() {
+                    *behavior = *followup;
+                    continue;
+                } // else branch follows through to select a behavior
+            } else {
+                continue;
+            }
+        }
+        let perception = perceiver.perceive(entity, transform.translation(), rifle_holder);
+        let best_candidate = brain
+            .candidates
+            .iter()
+            .filter_map(|candidate| {
+                let mut score = candidate.score(&perception, &behavior)?;
+                if candidate.is_current(&behavior) {
+                    score += HYSTERESIS;
+                }
+                Some((candidate, score))
+            })
+            .max_by_key(|(_, score)| FloatOrd(*score));
+        let Some((candidate, _)) = best_candidate else { continue };
+        if candidate.is_current(&behavior) {
+            candidate.update(&perception, &mut behavior);
+        } else {
+            *behavior = candidate.start(&perception, &behavior, &mut rng);
+        }
+    }
+}
@@ -5,29 +5,38 @@ use bevy_tnua::{
     TnuaPlatformerConfig, TnuaPlatformerControls,
 };
 use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::animation::{GltfSceneHandler, HumanAnimationState};
-use crate::bumpin::{BumpInitiator, BumpStatus};
+use crate::bumpin::{BumpConfig, BumpInitiator, BumpStatus, PreStepVelocity, ShoveCommand, Shover};
 use crate::camera::CameraFollow;
 use crate::crosshair::Aimedatable;
-use crate::killing::Killable;
+use crate::headless::VisualAssets;
+use crate::killing::{Health, Killable};
+use crate::level::{CurrentLevel, DamageRules, Level};
 use crate::level_reloading::{CleanOnLevelReload, LevelPopulationSet};
 use crate::menu::AppState;
-use crate::rifle::{AimElevation, RifleHolder, ShootCommand};
+use crate::replay::ReplayPlayback;
+use crate::rifle::{AimElevation, RifleHolder, ShootCommand, ThrowCommand};
 use crate::score::ScoreHaver;
+use crate::utils::project_by_normal;
 use crate::{collision_groups, ShootingSequenceSet};
 
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugin(InputManagerPlugin::<PlayerAction>::default());
+        app.add_system(setup_player.in_set(LevelPopulationSet));
         app.add_system({
-            setup_player
-                .in_schedule(OnEnter(AppState::LoadLevel))
-                .in_set(LevelPopulationSet)
+            read_player_input
+                .in_set(OnUpdate(AppState::Game))
+                .run_if(not(resource_exists::<ReplayPlayback>()))
+        });
+        app.add_system({
+            player_controls
+                .in_set(ShootingSequenceSet::ShootInitiator)
+                .in_schedule(CoreSchedule::FixedUpdate)
         });
-        app.add_system(player_controls.in_set(ShootingSequenceSet::ShootInitiator));
     }
 }
 
@@ -35,24 +44,55 @@ impl Plugin for PlayerPlugin {
 pub struct IsPlayer;
 
 #[derive(Actionlike, Clone, Debug)]
-enum PlayerAction {
+pub enum PlayerAction {
     Run,
     Jump,
     TurnWithMouse,
     TurnWithGamepad,
     Shoot,
+    Throw,
+    Shove,
 }
 
-fn setup_player(mut commands: Commands, asset_server: Res<AssetServer>) {
+/// The player's input for a single gameplay step, as read from the [`ActionState<PlayerAction>`]
+/// (or from a replay).
+///
+/// The input is read every frame but consumed on the fixed timestep, so the one-shot parts (mouse
+/// motion, shooting, throwing and shoving) accumulate until the next step consumes them.
+#[derive(Component, Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct PlayerInput {
+    pub run: Vec2,
+    pub turn_with_mouse: Vec2,
+    pub turn_with_gamepad: Vec2,
+    pub jump: Option<f32>,
+    pub shoot: bool,
+    #[serde(default)]
+    pub throw: bool,
+    #[serde(default)]
+    pub shove: bool,
+}
+
+fn setup_player(
+    mut commands: Commands,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+    visuals: VisualAssets,
+) {
+    let Some(level) = levels.get(&current_level.handle) else { return };
+    let mut transform = Transform::from_translation(level.player.position);
+    let facing = project_by_normal(level.player.facing, Vec3::Y);
+    if facing != Vec3::ZERO {
+        transform.look_to(facing, Vec3::Y);
+    }
     let mut cmd = commands.spawn_empty();
     cmd.insert(CleanOnLevelReload);
     cmd.insert(SceneBundle {
-        scene: asset_server.load("human.glb#Scene0"),
-        transform: Transform::from_xyz(0.0, 2.0, 0.0),
+        scene: visuals.load("human.glb#Scene0"),
+        transform,
         ..Default::default()
     });
     cmd.insert(GltfSceneHandler {
-        names_from: asset_server.load("human.glb"),
+        names_from: visuals.load("human.glb"),
     });
 
     cmd.insert(RigidBody::Dynamic);
@@ -92,13 +132,20 @@ fn setup_player(mut commands: Commands, asset_server: Res<AssetServer>) {
 
     cmd.insert(BumpInitiator);
     cmd.insert(BumpStatus::default());
+    cmd.insert(PreStepVelocity::default());
+    cmd.insert(BumpConfig::default());
+    cmd.insert(Shover::default());
     cmd.insert(RifleHolder::NoRifle);
     cmd.insert(AimElevation(0.0));
     cmd.insert(Killable { killed: false });
+    if let DamageRules::Health(rules) = &level.rules.damage {
+        cmd.insert(Health::new(rules.clone()));
+    }
     cmd.insert(ScoreHaver::new("Player"));
     cmd.insert(Aimedatable::default());
     cmd.insert(IsPlayer);
 
+    cmd.insert(PlayerInput::default());
     cmd.insert(InputManagerBundle::<PlayerAction> {
         action_state: ActionState::default(),
         input_map: {
@@ -107,6 +154,8 @@ fn setup_player(mut commands: Commands, asset_server: Res<AssetServer>) {
             input_map.insert(KeyCode::Space, PlayerAction::Jump);
             input_map.insert(DualAxis::mouse_motion(), PlayerAction::TurnWithMouse);
             input_map.insert(MouseButton::Left, PlayerAction::Shoot);
+            input_map.insert(MouseButton::Right, PlayerAction::Throw);
+            input_map.insert(KeyCode::E, PlayerAction::Shove);
             #[cfg(not(target_arch = "wasm32"))]
             {
                 input_map.insert(VirtualDPad::dpad(), PlayerAction::Run);
@@ -117,75 +166,116 @@ fn setup_player(mut commands: Commands, asset_server: Res<AssetServer>) {
                 input_map.insert(DualAxis::right_stick(), PlayerAction::TurnWithGamepad);
                 input_map.insert(GamepadButtonType::RightTrigger, PlayerAction::Shoot);
                 input_map.insert(GamepadButtonType::RightTrigger2, PlayerAction::Shoot);
+                input_map.insert(GamepadButtonType::West, PlayerAction::Throw);
+                input_map.insert(GamepadButtonType::East, PlayerAction::Shove);
             }
             input_map
         },
     });
 
     cmd.insert(CameraFollow {
-        direction: -Vec3::Z,
+        direction: level.player.facing,
     });
 }
 
+fn read_player_input(mut query: Query<(&ActionState<PlayerAction>, &mut PlayerInput)>) {
+    for (action_state, mut player_input) in query.iter_mut() {
+        let axis_pair = |action: PlayerAction| {
+            action_state
+                .axis_pair(action)
+                .map_or(Vec2::ZERO, |turn| Vec2::new(turn.x(), turn.y()))
+        };
+        player_input.run = action_state
+            .clamped_axis_pair(PlayerAction::Run)
+            .map_or(Vec2::ZERO, |run| Vec2::new(run.x(), run.y()));
+        player_input.turn_with_mouse += axis_pair(PlayerAction::TurnWithMouse);
+        player_input.turn_with_gamepad = axis_pair(PlayerAction::TurnWithGamepad);
+        player_input.jump = {
+            let action_data = action_state.action_data(PlayerAction::Jump);
+            if action_data.state.pressed() {
+                Some(action_data.value)
+            } else {
+                None
+            }
+        };
+        player_input.shoot |= action_state.just_pressed(PlayerAction::Shoot);
+        player_input.throw |= action_state.just_pressed(PlayerAction::Throw);
+        player_input.shove |= action_state.just_pressed(PlayerAction::Shove);
+    }
+}
+
 #[allow(clippy::type_complexity)]
 fn player_controls(
-    time: Res<Time>,
+    fixed_time: Res<FixedTime>,
     mut query: Query<(
         Entity,
-        &ActionState<PlayerAction>,
+        &mut PlayerInput,
         &mut TnuaPlatformerControls,
         &mut CameraFollow,
         &mut AimElevation,
         &RifleHolder,
     )>,
     mut shoot_commands_writer: EventWriter<ShootCommand>,
+    mut throw_commands_writer: EventWriter<ThrowCommand>,
+    mut shove_commands_writer: EventWriter<ShoveCommand>,
 ) {
-    for (entity, action_state, mut controls, mut camera_follow, mut aim_elevation, rifle_holder) in
-        query.iter_mut()
+    let delta_seconds = fixed_time.period.as_secs_f32();
+    for (
+        entity,
+        mut player_input,
+        mut controls,
+        mut camera_follow,
+        mut aim_elevation,
+        rifle_holder,
+    ) in query.iter_mut()
     {
         let turn: Vec2 = [
-            (Vec2::new(0.1, -0.05), PlayerAction::TurnWithMouse),
-            (Vec2::new(2.0, 2.0), PlayerAction::TurnWithGamepad),
+            (Vec2::new(0.1, -0.05), player_input.turn_with_mouse),
+            (Vec2::new(2.0, 2.0), player_input.turn_with_gamepad),
         ]
         .into_iter()
-        .filter_map(|(factor, action)| {
-            let turn = action_state.axis_pair(action)?;
-            Some(Vec2::new(factor.x * turn.x(), factor.y * turn.y()))
-        })
+        .map(|(factor, turn)| factor * turn)
         .sum();
         let turn_to_direction =
-            Quat::from_rotation_y(time.delta_seconds() * -turn.x).mul_vec3(camera_follow.direction);
+            Quat::from_rotation_y(delta_seconds * -turn.x).mul_vec3(camera_follow.direction);
         camera_follow.direction = turn_to_direction;
         controls.desired_forward = turn_to_direction;
 
-        aim_elevation.0 += time.delta_seconds() * turn.y;
+        aim_elevation.0 += delta_seconds * turn.y;
         aim_elevation.0 = aim_elevation.0.clamp(-0.5, 0.5);
 
         let sideway = camera_follow.direction.cross(Vec3::Y);
 
-        let direction = if let Some(axis_pair) = action_state.clamped_axis_pair(PlayerAction::Run) {
-            (axis_pair.x() * sideway + camera_follow.direction * axis_pair.y())
-                .clamp_length_max(1.0)
-        } else {
-            Vec3::ZERO
-        };
+        let direction = (player_input.run.x * sideway
+            + camera_follow.direction * player_input.run.y)
+            .clamp_length_max(1.0);
         controls.desired_velocity = direction;
-        controls.jump = {
-            let action_data = action_state.action_data(PlayerAction::Jump);
-            if action_data.state.pressed() {
-                Some(action_data.value)
-            } else {
-                None
-            }
-        };
+        controls.jump = player_input.jump;
 
-        if action_state.just_pressed(PlayerAction::Shoot) {
+        if player_input.shoot {
             if let RifleHolder::HasRifle(rifle) = rifle_holder {
                 shoot_commands_writer.send(ShootCommand {
                     rifle: *rifle,
                     shooter: entity,
+                    aim_deviation: Quat::IDENTITY,
+                })
+            }
+        }
+        if player_input.throw {
+            if let RifleHolder::HasRifle(rifle) = rifle_holder {
+                throw_commands_writer.send(ThrowCommand {
+                    rifle: *rifle,
+                    thrower: entity,
                 })
             }
         }
+        if player_input.shove {
+            shove_commands_writer.send(ShoveCommand { shover: entity });
+        }
+
+        player_input.turn_with_mouse = Vec2::ZERO;
+        player_input.shoot = false;
+        player_input.throw = false;
+        player_input.shove = false;
     }
 }
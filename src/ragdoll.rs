@@ -0,0 +1,171 @@
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use bevy_rapier3d::prelude::*;
+
+use crate::animation::AnimationsHandler;
+use crate::killing::{DamageSource, DeathEvent, KillingSet};
+use crate::level_reloading::CleanOnLevelReload;
+use crate::{collision_groups, GameplaySet};
+
+pub struct RagdollPlugin;
+
+impl Plugin for RagdollPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system({
+            spawn_ragdolls
+                .after(KillingSet)
+                .in_set(GameplaySet)
+                .in_schedule(CoreSchedule::FixedUpdate)
+        });
+    }
+}
+
+struct RagdollBoneSpec {
+    bone: &'static str,
+    length: f32,
+    radius: f32,
+}
+
+impl RagdollBoneSpec {
+    const fn new(bone: &'static str, length: f32, radius: f32) -> Self {
+        Self {
+            bone,
+            length,
+            radius,
+        }
+    }
+}
+
+/// The bones of `human.glb` that become rigid bodies. Bones point along their local Y axis.
+const HUMAN_RAGDOLL: &[RagdollBoneSpec] = &[
+    RagdollBoneSpec::new("Bone", 0.2, 0.3),
+    // Spine and head
+    RagdollBoneSpec::new("Bone.001", 0.4, 0.5),
+    RagdollBoneSpec::new("Bone.002", 0.4, 0.5),
+    RagdollBoneSpec::new("Bone.003", 0.4, 0.35),
+    // Arms
+    RagdollBoneSpec::new("Bone.012", 0.5, 0.15),
+    RagdollBoneSpec::new("Bone.014", 0.5, 0.15),
+    RagdollBoneSpec::new("Bone.016", 0.3, 0.15),
+    RagdollBoneSpec::new("Bone.017", 0.3, 0.1),
+    RagdollBoneSpec::new("Bone.013", 0.5, 0.15),
+    RagdollBoneSpec::new("Bone.015", 0.5, 0.15),
+    RagdollBoneSpec::new("Bone.018", 0.3, 0.15),
+    RagdollBoneSpec::new("Bone.019", 0.3, 0.1),
+    // Legs
+    RagdollBoneSpec::new("Bone.004", 0.2, 0.2),
+    RagdollBoneSpec::new("Bone.006", 0.3, 0.2),
+    RagdollBoneSpec::new("Bone.008", 1.0, 0.2),
+    RagdollBoneSpec::new("Bone.010", 1.0, 0.2),
+    RagdollBoneSpec::new("Bone.005", 0.2, 0.2),
+    RagdollBoneSpec::new("Bone.007", 0.3, 0.2),
+    RagdollBoneSpec::new("Bone.009", 1.0, 0.2),
+    RagdollBoneSpec::new("Bone.011", 1.0, 0.2),
+];
+
+/// A bone that was turned into a rigid body when its participant died.
+#[derive(Component)]
+pub struct RagdollBone;
+
+struct BoneToSpawn {
+    entity: Entity,
+    spec: &'static RagdollBoneSpec,
+    local_transform: Transform,
+    global_transform: GlobalTransform,
+    parent: Option<Entity>,
+}
+
+/// Detach the bones of the dead participant's model from its hierarchy and let physics move them
+/// instead of the `AnimationPlayer`. The participant's own capsule keeps being simulated as before
+/// (it is all there is when running headless), but it does not collide with the bones.
+fn spawn_ragdolls(
+    mut reader: EventReader<DeathEvent>,
+    victims_query: Query<(&Velocity, Option<&AnimationsHandler>)>,
+    children_query: Query<&Children>,
+    bones_query: Query<(&Name, &Transform, &GlobalTransform, Option<&Parent>)>,
+    mut commands: Commands,
+) {
+    for DeathEvent { victim, source, .. } in reader.iter() {
+        let Ok((velocity, animations_handler)) = victims_query.get(*victim) else { continue };
+        let bones = children_query
+            .iter_descendants(*victim)
+            .filter_map(|entity| {
+                let (name, local_transform, global_transform, parent) =
+                    bones_query.get(entity).ok()?;
+                let spec = HUMAN_RAGDOLL
+                    .iter()
+                    .find(|spec| spec.bone == name.as_str())?;
+                Some(BoneToSpawn {
+                    entity,
+                    spec,
+                    local_transform: *local_transform,
+                    global_transform: *global_transform,
+                    parent: parent.map(|parent| parent.get()),
+                })
+            })
+            .collect::<Vec<_>>();
+        // The model's scene was not spawned yet (or never will, when running headless)
+        if bones.is_empty() {
+            continue;
+        }
+
+        if let Some(animations_handler) = animations_handler {
+            commands
+                .entity(animations_handler.animation_player_entity)
+                .remove::<AnimationPlayer>();
+        }
+
+        let bone_entities = bones.iter().map(|bone| bone.entity).collect::<HashSet<_>>();
+        for bone in bones.iter() {
+            let mut cmd = commands.entity(bone.entity);
+            // Bodies with parents are written back relative to the parent's transform, which is
+            // not updated in time when the parent is a body too.
+            cmd.remove_parent_in_place();
+            cmd.insert(CleanOnLevelReload);
+            cmd.insert(RagdollBone);
+            cmd.insert(RigidBody::Dynamic);
+            cmd.insert(Velocity::linear(velocity.linvel));
+            cmd.insert(Collider::compound(vec![(
+                Vec3::Y * 0.5 * bone.spec.length,
+                Quat::IDENTITY,
+                Collider::capsule_y(0.5 * bone.spec.length, bone.spec.radius),
+            )]));
+            cmd.insert(SolverGroups {
+                memberships: collision_groups::PARTICIPANT,
+                filters: collision_groups::GENERAL,
+            });
+            let Some(parent) = bone.parent else { continue };
+            // The root bone's parent is the armature
+            if bone_entities.contains(&parent) {
+                cmd.insert(ImpulseJoint::new(
+                    parent,
+                    SphericalJointBuilder::new()
+                        .local_anchor1(bone.local_transform.translation)
+                        .local_anchor2(Vec3::ZERO),
+                ));
+            }
+        }
+
+        let DamageSource::Bullet { position, momentum, .. } = source else { continue };
+        // The bullet's momentum goes to the bone closest to where it hit
+        let closest_bone = bones
+            .iter()
+            .map(|bone| {
+                let center = bone
+                    .global_transform
+                    .transform_point(Vec3::Y * 0.5 * bone.spec.length);
+                (bone.entity, center)
+            })
+            .min_by(|(_, a), (_, b)| {
+                a.distance_squared(*position)
+                    .total_cmp(&b.distance_squared(*position))
+            });
+        if let Some((hit_bone, bone_center)) = closest_bone {
+            commands.entity(hit_bone).insert(ExternalImpulse::at_point(
+                *momentum,
+                *position,
+                bone_center,
+            ));
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use bevy_rapier3d::prelude::{RapierConfiguration, TimestepMode};
+use bevy_turborand::{DelegatedRng, GlobalRng};
+use float_ord::FloatOrd;
+use serde::{Deserialize, Serialize};
+
+use crate::difficulty::{Difficulty, SelectedDifficulty};
+use crate::killing::Killable;
+use crate::level::{Level, LevelSelection};
+use crate::menu::AppState;
+use crate::player::{IsPlayer, PlayerInput};
+use crate::rifle::RifleStatus;
+use crate::{GameplaySet, ShootingSequenceSet, GAMEPLAY_TIMESTEP};
+
+/// Records the player's input, or plays back a recorded match, depending on whether a
+/// [`ReplayRecorder`] or a [`ReplayPlayback`] resource is present.
+///
+/// Both make every frame advance the simulation by exactly one fixed step, so that the frames of
+/// the recording and of the playback match.
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReplayTrackedEntities>();
+        app.add_startup_system(use_replay_timestep);
+        app.add_system(start_replay.in_schedule(OnEnter(AppState::LoadLevel)));
+        app.add_systems(
+            (
+                checkpoint_tracked_entities,
+                record_player_input.run_if(resource_exists::<ReplayRecorder>()),
+                feed_recorded_input.run_if(resource_exists::<ReplayPlayback>()),
+            )
+                .chain()
+                .in_set(GameplaySet)
+                .before(ShootingSequenceSet::ShootInitiator)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+        for state in [AppState::GameOver, AppState::LevelCompleted] {
+            app.add_system({
+                write_replay
+                    .in_schedule(OnEnter(state))
+                    .run_if(resource_exists::<ReplayRecorder>())
+            });
+        }
+    }
+}
+
+/// Positions of the tracked entities are stored every this many frames.
+const CHECKPOINT_INTERVAL: usize = 30;
+
+/// Playback is considered diverged when a tracked entity is farther than this from where it was
+/// recorded.
+const DIVERGENCE_TOLERANCE: f32 = 0.01;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Replay {
+    /// `GlobalRng` is reseeded with this when the level is loaded.
+    pub seed: u64,
+    pub timestep: f32,
+    pub level: Level,
+    /// The difficulty selected in the menu during the recording.
+    #[serde(default)]
+    pub difficulty: Difficulty,
+    pub frames: Vec<ReplayFrame>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReplayFrame {
+    pub input: PlayerInput,
+    /// Transforms of the participants and the rifles, taken before the step's input is applied.
+    #[serde(default)]
+    pub checkpoint: Option<Vec<(Vec3, Quat)>>,
+}
+
+#[derive(Resource)]
+pub struct ReplayRecorder {
+    path: PathBuf,
+    /// `None` when no match is being recorded.
+    seed: Option<u64>,
+    frames: Vec<ReplayFrame>,
+    pending_checkpoint: Option<Vec<(Vec3, Quat)>>,
+}
+
+impl ReplayRecorder {
+    /// The replay is written to `path` when the match ends.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            seed: None,
+            frames: Vec::new(),
+            pending_checkpoint: None,
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    replay: Replay,
+    current_frame: usize,
+    divergence: Option<ReplayDivergence>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReplayDivergence {
+    pub frame: usize,
+    pub tracked_entity: usize,
+    pub recorded: Vec3,
+    pub actual: Vec3,
+}
+
+impl ReplayPlayback {
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = std::fs::File::open(path.into())?;
+        Ok(Self {
+            replay: ron::de::from_reader(file)?,
+            current_frame: 0,
+            divergence: None,
+        })
+    }
+
+    /// Whether all the recorded frames were played.
+    pub fn finished(&self) -> bool {
+        self.replay.frames.len() <= self.current_frame
+    }
+
+    /// The first point where playback did not match the recording.
+    pub fn divergence(&self) -> Option<&ReplayDivergence> {
+        self.divergence.as_ref()
+    }
+}
+
+/// The entities whose transforms are checkpointed, in a stable order.
+#[derive(Resource, Default)]
+struct ReplayTrackedEntities(Option<Vec<Entity>>);
+
+/// Playback runs exactly one recorded step per frame. Recording keeps real time - the input is
+/// recorded per fixed step anyway, however many of them a frame runs.
+fn use_replay_timestep(
+    mut commands: Commands,
+    playback: Option<Res<ReplayPlayback>>,
+    mut fixed_time: ResMut<FixedTime>,
+    mut rapier_configuration: ResMut<RapierConfiguration>,
+) {
+    let Some(playback) = playback else { return };
+    let timestep = playback.replay.timestep;
+    commands.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+        timestep,
+    )));
+    *fixed_time = FixedTime::new_from_secs(timestep);
+    rapier_configuration.timestep_mode = TimestepMode::Fixed {
+        dt: timestep,
+        substeps: 1,
+    };
+}
+
+fn start_replay(
+    mut global_rng: ResMut<GlobalRng>,
+    recorder: Option<ResMut<ReplayRecorder>>,
+    playback: Option<ResMut<ReplayPlayback>>,
+    mut tracked_entities: ResMut<ReplayTrackedEntities>,
+    mut level_selection: LevelSelection,
+    mut levels: ResMut<Assets<Level>>,
+    mut selected_difficulty: ResMut<SelectedDifficulty>,
+) {
+    tracked_entities.0 = None;
+    if let Some(mut playback) = playback {
+        *global_rng = GlobalRng::with_seed(playback.replay.seed);
+        level_selection.replace_current(levels.add(playback.replay.level.clone()));
+        selected_difficulty.0 = playback.replay.difficulty.clone();
+        playback.current_frame = 0;
+        playback.divergence = None;
+    } else if let Some(mut recorder) = recorder {
+        let seed = global_rng.u64(..);
+        *global_rng = GlobalRng::with_seed(seed);
+        recorder.seed = Some(seed);
+        recorder.frames.clear();
+        recorder.pending_checkpoint = None;
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn checkpoint_tracked_entities(
+    mut tracked_entities: ResMut<ReplayTrackedEntities>,
+    query: Query<(Entity, &GlobalTransform), Or<(With<Killable>, With<RifleStatus>)>>,
+    recorder: Option<ResMut<ReplayRecorder>>,
+    playback: Option<ResMut<ReplayPlayback>>,
+) {
+    let frame = if let Some(playback) = playback.as_ref() {
+        playback.current_frame
+    } else if let Some(recorder) = recorder.as_ref().filter(|recorder| recorder.seed.is_some()) {
+        recorder.frames.len()
+    } else {
+        return;
+    };
+    if frame % CHECKPOINT_INTERVAL != 0 {
+        return;
+    }
+
+    // Entity IDs differ between runs, but on the first frame everything is still where the level
+    // (and the seeded RNG) placed it.
+    let tracked_entities = tracked_entities.0.get_or_insert_with(|| {
+        let mut entities = query
+            .iter()
+            .map(|(entity, transform)| (entity, transform.translation()))
+            .collect::<Vec<_>>();
+        entities.sort_by_key(|(_, position)| {
+            (
+                FloatOrd(position.x),
+                FloatOrd(position.y),
+                FloatOrd(position.z),
+            )
+        });
+        entities.into_iter().map(|(entity, _)| entity).collect()
+    });
+    let checkpoint = tracked_entities
+        .iter()
+        .map(|entity| {
+            let Ok((_, transform)) = query.get(*entity) else { return (Vec3::NAN, Quat::NAN) };
+            let (_, rotation, translation) = transform.to_scale_rotation_translation();
+            (translation, rotation)
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(mut playback) = playback {
+        if playback.divergence.is_some() {
+            return;
+        }
+        let Some(ReplayFrame { checkpoint: Some(recorded), .. }) = playback.replay.frames.get(frame) else { return };
+        let divergence = recorded
+            .iter()
+            .zip(checkpoint.iter())
+            .enumerate()
+            .find(|(_, ((recorded, _), (actual, _)))| {
+                // NaN (a despawned entity) never equals anything, so compare these separately
+                recorded.is_nan() != actual.is_nan()
+                    || DIVERGENCE_TOLERANCE < recorded.distance(*actual)
+            })
+            .map(
+                |(tracked_entity, ((recorded, _), (actual, _)))| ReplayDivergence {
+                    frame,
+                    tracked_entity,
+                    recorded: *recorded,
+                    actual: *actual,
+                },
+            );
+        if let Some(divergence) = divergence {
+            warn!("Replay diverged: {:?}", divergence);
+            playback.divergence = Some(divergence);
+        }
+    } else if let Some(mut recorder) = recorder {
+        recorder.pending_checkpoint = Some(checkpoint);
+    }
+}
+
+fn record_player_input(
+    mut recorder: ResMut<ReplayRecorder>,
+    query: Query<&PlayerInput, With<IsPlayer>>,
+) {
+    if recorder.seed.is_none() {
+        return;
+    }
+    let Ok(input) = query.get_single() else { return };
+    let checkpoint = recorder.pending_checkpoint.take();
+    recorder.frames.push(ReplayFrame {
+        input: input.clone(),
+        checkpoint,
+    });
+}
+
+fn feed_recorded_input(
+    mut playback: ResMut<ReplayPlayback>,
+    mut query: Query<&mut PlayerInput, With<IsPlayer>>,
+) {
+    let Ok(mut input) = query.get_single_mut() else { return };
+    *input = if let Some(frame) = playback.replay.frames.get(playback.current_frame) {
+        frame.input.clone()
+    } else {
+        PlayerInput::default()
+    };
+    playback.current_frame += 1;
+}
+
+fn write_replay(
+    mut recorder: ResMut<ReplayRecorder>,
+    level_selection: LevelSelection,
+    levels: Res<Assets<Level>>,
+    selected_difficulty: Res<SelectedDifficulty>,
+) {
+    let Some(seed) = recorder.seed.take() else { return };
+    let Some(level) = levels.get(&level_selection.current().handle) else { return };
+    let replay = Replay {
+        seed,
+        timestep: GAMEPLAY_TIMESTEP,
+        level: level.clone(),
+        difficulty: selected_difficulty.0.clone(),
+        frames: std::mem::take(&mut recorder.frames),
+    };
+    let result = std::fs::File::create(&recorder.path)
+        .map_err(ron::Error::from)
+        .and_then(|file| ron::ser::to_writer(file, &replay));
+    match result {
+        Ok(()) => info!("Replay written to {:?}", recorder.path),
+        Err(err) => error!("Failed to write replay to {:?}: {}", recorder.path, err),
+    }
+}
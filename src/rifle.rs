@@ -6,28 +6,48 @@ use bevy_turborand::prelude::*;
 use float_ord::FloatOrd;
 
 use crate::arena::Ground;
+use crate::bullet::{BulletImpact, BulletImpactSet, BulletImpactTargetKind};
+use crate::bumpin::{BumpConfig, BumpEvent, BumpSet, BumpStatus};
+use crate::headless::VisualAssets;
+use crate::level::{CurrentLevel, Level, RifleSpawn};
 use crate::level_reloading::{CleanOnLevelReload, LevelPopulationSet};
-use crate::menu::AppState;
 use crate::player::IsPlayer;
-use crate::utils::entities_ordered_by_type;
-use crate::{collision_groups, ShootingSequenceSet};
+use crate::utils::{entities_ordered_by_type, project_by_normal};
+use crate::weapon::{FlingRule, LoadedWeapons, Weapon, WeaponDefinition};
+use crate::{collision_groups, GameplaySet, ShootingSequenceSet};
 
 pub struct RiflePlugin;
 
 impl Plugin for RiflePlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<ShootCommand>();
-        app.add_system({
-            setup_rifle
-                .in_schedule(OnEnter(AppState::LoadLevel))
-                .in_set(LevelPopulationSet)
-        });
+        app.add_event::<ThrowCommand>();
+        app.add_system(setup_rifle.in_set(LevelPopulationSet));
 
         app.add_systems(
             (handle_rifle_collisions, pose_rifle, attract_to_player)
-                .in_set(OnUpdate(AppState::Game)),
+                .in_set(GameplaySet)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+        app.add_system({
+            knock_rifles_loose
+                .in_set(GameplaySet)
+                .after(BumpSet)
+                .after(handle_rifle_collisions)
+                .in_schedule(CoreSchedule::FixedUpdate)
+        });
+        app.add_system({
+            knock_floating_rifles
+                .in_set(GameplaySet)
+                .after(BulletImpactSet)
+                .in_schedule(CoreSchedule::FixedUpdate)
+        });
+        app.add_systems(
+            (handle_shooting, handle_throwing)
+                .chain()
+                .in_set(ShootingSequenceSet::RifleRecoil)
+                .in_schedule(CoreSchedule::FixedUpdate),
         );
-        app.add_system(handle_shooting.in_set(ShootingSequenceSet::RifleRecoil));
     }
 }
 
@@ -47,62 +67,164 @@ pub enum RifleStatus {
     Floating,
     Equiped(Entity),
     Cooldown(Timer),
+    /// In flight after being thrown. Bumps the first participant it hits, other than the thrower.
+    Thrown {
+        thrower: Entity,
+    },
+}
+
+/// Rounds left in a rifle. Rifles without a magazine fire a single shot.
+#[derive(Component)]
+pub struct Magazine {
+    pub rounds: usize,
+    pub capacity: usize,
+    fling: FlingRule,
+}
+
+impl Magazine {
+    pub fn can_fire(&self) -> bool {
+        0 < self.rounds
+    }
+
+    /// Consume a round, if there is one. Returns `true` if the rifle should be flung.
+    fn fire(&mut self) -> bool {
+        if self.rounds == 0 {
+            return true;
+        }
+        self.rounds -= 1;
+        self.rounds == 0 && self.fling == FlingRule::WithLastShot
+    }
+}
+
+/// How many shots a rifle can still fire before it's flung.
+pub fn rounds_left(magazine: Option<&Magazine>) -> usize {
+    magazine.map_or(1, |magazine| magazine.rounds)
 }
 
 pub struct ShootCommand {
     pub shooter: Entity,
     pub rifle: Entity,
+    /// Rotates the shot away from where the rifle is aimed, in the rifle's coordinates.
+    pub aim_deviation: Quat,
 }
 
-fn setup_rifle(mut commands: Commands, asset_server: Res<AssetServer>, mut rng: ResMut<GlobalRng>) {
-    let mut cmd = commands.spawn_empty();
-    cmd.insert(CleanOnLevelReload);
-    cmd.insert(SceneBundle {
-        scene: asset_server.load("rifle.glb#Scene0"),
-        transform: Transform::from_xyz(
-            10.0 * rng.f32_normalized(),
-            10.0,
-            10.0 * rng.f32_normalized(),
-        ),
-        ..Default::default()
-    });
+pub struct ThrowCommand {
+    pub thrower: Entity,
+    pub rifle: Entity,
+}
 
-    cmd.insert(RigidBody::Dynamic);
-    cmd.insert(Velocity::default());
-    cmd.insert(Collider::cuboid(0.1, 0.1, 1.5));
-    cmd.insert(ActiveEvents::COLLISION_EVENTS);
-    cmd.insert(SolverGroups {
-        memberships: collision_groups::WEAPON,
-        filters: collision_groups::GENERAL,
-    });
+fn setup_rifle(
+    mut commands: Commands,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+    loaded_weapons: Res<LoadedWeapons>,
+    weapon_definitions: Res<Assets<WeaponDefinition>>,
+    visuals: VisualAssets,
+    mut rng: ResMut<GlobalRng>,
+) {
+    let Some(level) = levels.get(&current_level.handle) else { return };
+    for RifleSpawn {
+        position,
+        scatter,
+        projectile,
+        weapon,
+    } in level.rifles.iter()
+    {
+        let Some(weapon_handle) = loaded_weapons.handle(weapon) else { continue };
+        let Some(definition) = weapon_definitions.get(weapon_handle) else { continue };
+        let mut cmd = commands.spawn_empty();
+        cmd.insert(CleanOnLevelReload);
+        cmd.insert(SceneBundle {
+            scene: visuals.load(&definition.model),
+            transform: Transform::from_translation(
+                *position + *scatter * Vec3::new(rng.f32_normalized(), 0.0, rng.f32_normalized()),
+            ),
+            ..Default::default()
+        });
+
+        cmd.insert(RigidBody::Dynamic);
+        cmd.insert(Velocity::default());
+        let half_extents = definition.collider_half_extents;
+        cmd.insert(Collider::cuboid(
+            half_extents.x,
+            half_extents.y,
+            half_extents.z,
+        ));
+        cmd.insert(ActiveEvents::COLLISION_EVENTS);
+        cmd.insert(SolverGroups {
+            memberships: collision_groups::WEAPON,
+            filters: collision_groups::GENERAL,
+        });
 
-    cmd.insert(RifleStatus::Floating);
+        cmd.insert(RifleStatus::Floating);
+        cmd.insert(*projectile);
+        cmd.insert(Weapon(weapon_handle.clone()));
+        if let Some(magazine) = &definition.magazine {
+            cmd.insert(Magazine {
+                rounds: magazine.rounds,
+                capacity: magazine.rounds,
+                fling: magazine.fling,
+            });
+        }
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn handle_rifle_collisions(
     mut reader: EventReader<CollisionEvent>,
-    mut rifles_query: Query<&mut RifleStatus>,
+    mut rifles_query: Query<(&mut RifleStatus, &Weapon, Option<&mut Magazine>, &Velocity)>,
+    weapon_definitions: Res<Assets<WeaponDefinition>>,
     ground_query: Query<&Ground>,
     mut rifle_holder_query: Query<&mut RifleHolder>,
+    mut bump_status_query: Query<&mut BumpStatus>,
+    mut bump_events_writer: EventWriter<BumpEvent>,
     mut commands: Commands,
 ) {
     for event in reader.iter() {
         let CollisionEvent::Started(e1, e2, _) = event else { continue };
         let Some([rifle, other]) = entities_ordered_by_type!([*e1, *e2], rifles_query) else { continue };
 
-        let mut rifle_status = rifles_query.get_mut(rifle).unwrap();
+        let (mut rifle_status, weapon, magazine, velocity) = rifles_query.get_mut(rifle).unwrap();
         if matches!(*rifle_status, RifleStatus::Equiped(_)) {
             continue;
         }
+        let Some(definition) = weapon_definitions.get(&weapon.0) else { continue };
+        if let RifleStatus::Thrown { thrower } = *rifle_status {
+            if other == thrower {
+                continue;
+            }
+            if let Ok(mut bump_status) = bump_status_query.get_mut(other) {
+                let direction = project_by_normal(velocity.linvel, Vec3::Y).normalize_or_zero();
+                let impulse = definition.throw_bump_impulse * direction;
+                if bump_status.trigger_bump_if_empty(impulse) {
+                    bump_events_writer.send(BumpEvent {
+                        initiator: thrower,
+                        victim: other,
+                        impulse,
+                    });
+                }
+                *rifle_status = RifleStatus::Cooldown(Timer::from_seconds(
+                    definition.cooldown,
+                    TimerMode::Once,
+                ));
+                continue;
+            }
+        }
         if ground_query.contains(other) {
-            *rifle_status = RifleStatus::WaitBeforeFloat(Timer::from_seconds(0.5, TimerMode::Once));
+            *rifle_status = RifleStatus::WaitBeforeFloat(Timer::from_seconds(
+                definition.wait_before_float,
+                TimerMode::Once,
+            ));
         } else if let Ok(mut rifle_holder) = rifle_holder_query.get_mut(other) {
             if !matches!(*rifle_status, RifleStatus::Cooldown(_))
                 && matches!(*rifle_holder, RifleHolder::NoRifle)
             {
                 *rifle_status = RifleStatus::Equiped(other);
                 *rifle_holder = RifleHolder::HasRifle(rifle);
-                let joint = FixedJointBuilder::new().local_anchor1(Vec3::new(0.65, 0.0, 0.0));
+                if let Some(mut magazine) = magazine {
+                    magazine.rounds = magazine.capacity;
+                }
+                let joint = FixedJointBuilder::new().local_anchor1(definition.grip_anchor);
                 commands
                     .entity(rifle)
                     .insert(ImpulseJoint::new(other, joint));
@@ -112,7 +234,7 @@ fn handle_rifle_collisions(
 }
 
 fn pose_rifle(
-    time: Res<Time>,
+    fixed_time: Res<FixedTime>,
     holders_query: Query<&AimElevation>,
     mut rifles_query: Query<(
         &mut RifleStatus,
@@ -121,25 +243,22 @@ fn pose_rifle(
         Option<&mut ImpulseJoint>,
     )>,
 ) {
-    if time.delta_seconds() == 0.0 {
-        return;
-    }
     for (mut rifle_status, transform, mut velocity, joint) in rifles_query.iter_mut() {
         match rifle_status.as_mut() {
-            RifleStatus::Ragdoll => {
+            RifleStatus::Ragdoll | RifleStatus::Thrown { .. } => {
                 continue;
             }
             RifleStatus::WaitBeforeFloat(timer) => {
-                if timer.tick(time.delta()).finished() {
+                if timer.tick(fixed_time.period).finished() {
                     *rifle_status = RifleStatus::Floating;
                 }
             }
             RifleStatus::Floating => {
                 let (_, _, translation) = transform.to_scale_rotation_translation();
                 let desired_height = 2.0;
-                let one_frame_velocity =
-                    Vec3::Y * (desired_height - translation.y) / time.delta_seconds();
-                let desired_velocitry = one_frame_velocity.clamp_length_max(5.0);
+                let one_step_velocity =
+                    Vec3::Y * (desired_height - translation.y) / fixed_time.period.as_secs_f32();
+                let desired_velocitry = one_step_velocity.clamp_length_max(5.0);
                 velocity.linvel = desired_velocitry;
 
                 velocity.angvel = 2.0 * Vec3::Y;
@@ -154,7 +273,7 @@ fn pose_rifle(
                 }
             }
             RifleStatus::Cooldown(timer) => {
-                if timer.tick(time.delta()).finished() {
+                if timer.tick(fixed_time.period).finished() {
                     *rifle_status = RifleStatus::Ragdoll;
                 }
             }
@@ -164,19 +283,40 @@ fn pose_rifle(
 
 fn handle_shooting(
     mut reader: EventReader<ShootCommand>,
-    mut rifles_query: Query<(&mut RifleStatus, &GlobalTransform, &mut Velocity)>,
+    mut rifles_query: Query<(
+        &mut RifleStatus,
+        &Weapon,
+        Option<&mut Magazine>,
+        &GlobalTransform,
+        &mut Velocity,
+    )>,
+    weapon_definitions: Res<Assets<WeaponDefinition>>,
     mut holders_query: Query<&mut RifleHolder>,
     mut commands: Commands,
     mut rng: ResMut<GlobalRng>,
 ) {
-    for ShootCommand { rifle, shooter: _ } in reader.iter() {
-        let Ok((mut rifle_status, transform, mut velocity)) = rifles_query.get_mut(*rifle) else { continue };
+    for ShootCommand { shooter, rifle, .. } in reader.iter() {
+        let Ok((mut rifle_status, weapon, magazine, transform, mut velocity)) = rifles_query.get_mut(*rifle) else { continue };
+        let Some(definition) = weapon_definitions.get(&weapon.0) else { continue };
 
-        commands.entity(*rifle).remove::<ImpulseJoint>();
+        let RifleStatus::Equiped(holder_entity) = *rifle_status else {
+            commands.entity(*rifle).remove::<ImpulseJoint>();
+            continue;
+        };
+        if holder_entity != *shooter {
+            // Someone else picked it up since the command was sent
+            continue;
+        }
+        if let Some(mut magazine) = magazine {
+            if !magazine.fire() {
+                continue;
+            }
+        }
 
-        let RifleStatus::Equiped(holder_entity) = *rifle_status else { continue };
+        commands.entity(*rifle).remove::<ImpulseJoint>();
 
-        *rifle_status = RifleStatus::Cooldown(Timer::from_seconds(1.0, TimerMode::Once));
+        *rifle_status =
+            RifleStatus::Cooldown(Timer::from_seconds(definition.cooldown, TimerMode::Once));
         if let Ok(mut rifle_holder) = holders_query.get_mut(holder_entity) {
             *rifle_holder = RifleHolder::NoRifle;
         } else {
@@ -184,15 +324,93 @@ fn handle_shooting(
         }
 
         let (_, rotation, _) = transform.to_scale_rotation_translation();
+        let fling_velocity = definition.fling_velocity;
         velocity.linvel = rotation.mul_vec3(Vec3::new(
-            15.0 * rng.f32_normalized(),
-            20.0,
-            15.0 * rng.f32_normalized(),
+            fling_velocity.x * rng.f32_normalized(),
+            fling_velocity.y,
+            fling_velocity.z * rng.f32_normalized(),
         ));
         velocity.angvel = Quat::from_axis_angle(rotation.mul_vec3(Vec3::X), -PI).xyz() * 5.0;
     }
 }
 
+fn handle_throwing(
+    mut reader: EventReader<ThrowCommand>,
+    mut rifles_query: Query<(&mut RifleStatus, &Weapon, &GlobalTransform, &mut Velocity)>,
+    weapon_definitions: Res<Assets<WeaponDefinition>>,
+    mut holders_query: Query<&mut RifleHolder>,
+    mut commands: Commands,
+) {
+    for ThrowCommand { thrower, rifle } in reader.iter() {
+        let Ok((mut rifle_status, weapon, transform, mut velocity)) = rifles_query.get_mut(*rifle) else { continue };
+        let Some(definition) = weapon_definitions.get(&weapon.0) else { continue };
+        if !matches!(*rifle_status, RifleStatus::Equiped(holder) if holder == *thrower) {
+            continue;
+        }
+
+        commands.entity(*rifle).remove::<ImpulseJoint>();
+        *rifle_status = RifleStatus::Thrown { thrower: *thrower };
+        if let Ok(mut rifle_holder) = holders_query.get_mut(*thrower) {
+            *rifle_holder = RifleHolder::NoRifle;
+        }
+
+        velocity.linvel = definition.throw_speed * transform.forward();
+        velocity.angvel = Vec3::ZERO;
+    }
+}
+
+fn knock_rifles_loose(
+    mut reader: EventReader<BumpEvent>,
+    mut holders_query: Query<(&mut RifleHolder, Option<&BumpConfig>)>,
+    mut rifles_query: Query<(&mut RifleStatus, &Weapon)>,
+    weapon_definitions: Res<Assets<WeaponDefinition>>,
+    mut commands: Commands,
+) {
+    let default_config = BumpConfig::default();
+    for BumpEvent {
+        victim, impulse, ..
+    } in reader.iter()
+    {
+        let Ok((mut rifle_holder, config)) = holders_query.get_mut(*victim) else { continue };
+        let RifleHolder::HasRifle(rifle) = *rifle_holder else { continue };
+        if impulse.length() < config.unwrap_or(&default_config).disarm_impulse {
+            continue;
+        }
+        *rifle_holder = RifleHolder::NoRifle;
+        commands.entity(rifle).remove::<ImpulseJoint>();
+        let Ok((mut rifle_status, weapon)) = rifles_query.get_mut(rifle) else { continue };
+        // Like after shooting, so that the victim can't just pick it right back up
+        *rifle_status = match weapon_definitions.get(&weapon.0) {
+            Some(definition) => {
+                RifleStatus::Cooldown(Timer::from_seconds(definition.cooldown, TimerMode::Once))
+            }
+            None => RifleStatus::Ragdoll,
+        };
+    }
+}
+
+/// Floating rifles hold their position, so a bullet that hits one stops it floating for a while.
+fn knock_floating_rifles(
+    mut reader: EventReader<BulletImpact>,
+    mut rifles_query: Query<(&mut RifleStatus, &Weapon)>,
+    weapon_definitions: Res<Assets<WeaponDefinition>>,
+) {
+    for impact in reader.iter() {
+        if impact.target_kind != BulletImpactTargetKind::Loose {
+            continue;
+        }
+        let Ok((mut rifle_status, weapon)) = rifles_query.get_mut(impact.target) else { continue };
+        if !matches!(*rifle_status, RifleStatus::Floating) {
+            continue;
+        }
+        let Some(definition) = weapon_definitions.get(&weapon.0) else { continue };
+        *rifle_status = RifleStatus::WaitBeforeFloat(Timer::from_seconds(
+            definition.wait_before_float,
+            TimerMode::Once,
+        ));
+    }
+}
+
 fn attract_to_player(
     mut rifles_query: Query<(&RifleStatus, &GlobalTransform, &mut Velocity)>,
     players_query: Query<&GlobalTransform, With<IsPlayer>>,
@@ -1,13 +1,17 @@
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContexts};
 
-use crate::killing::Killable;
+use crate::killing::{Health, Killable};
+use crate::player::IsPlayer;
+use crate::rifle::{Magazine, RifleHolder};
 
 pub struct ScorePlugin;
 
 impl Plugin for ScorePlugin {
     fn build(&self, app: &mut App) {
         app.add_system(show_score);
+        app.add_system(show_ammo);
+        app.add_system(show_health);
     }
 }
 
@@ -15,6 +19,7 @@ impl Plugin for ScorePlugin {
 pub struct ScoreHaver {
     name: String,
     pub score: usize,
+    pub kills: usize,
 }
 
 impl ScoreHaver {
@@ -22,8 +27,13 @@ impl ScoreHaver {
         Self {
             name: name.to_owned(),
             score: 0,
+            kills: 0,
         }
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 }
 
 fn show_score(
@@ -59,3 +69,37 @@ fn show_score(
         }
     });
 }
+
+fn show_ammo(
+    mut egui_context: EguiContexts,
+    players_query: Query<&RifleHolder, With<IsPlayer>>,
+    magazines_query: Query<&Magazine>,
+) {
+    let Ok(RifleHolder::HasRifle(rifle)) = players_query.get_single() else { return };
+    let Ok(magazine) = magazines_query.get(*rifle) else { return };
+    let panel = egui::Area::new("ammo-area").anchor(egui::Align2::RIGHT_BOTTOM, [-10.0, -10.0]);
+    panel.show(egui_context.ctx_mut(), |ui| {
+        ui.label(
+            egui::RichText::new(format!(
+                "Rounds: {} / {}",
+                magazine.rounds, magazine.capacity
+            ))
+            .strong(),
+        );
+    });
+}
+
+fn show_health(mut egui_context: EguiContexts, players_query: Query<&Health, With<IsPlayer>>) {
+    let Ok(health) = players_query.get_single() else { return };
+    let panel = egui::Area::new("health-area").anchor(egui::Align2::LEFT_BOTTOM, [10.0, -10.0]);
+    panel.show(egui_context.ctx_mut(), |ui| {
+        ui.label(
+            egui::RichText::new(format!(
+                "Health: {:.0} / {:.0}",
+                health.current.max(0.0),
+                health.max()
+            ))
+            .strong(),
+        );
+    });
+}
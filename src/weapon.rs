@@ -0,0 +1,190 @@
+use bevy::asset::{AssetLoader, LoadContext, LoadState, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::{BoxedFuture, HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::level::{CurrentLevel, Level, RifleSpawn};
+use crate::level_reloading::LevelPopulationSet;
+use crate::menu::AppState;
+
+pub struct WeaponPlugin;
+
+impl Plugin for WeaponPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<WeaponDefinition>();
+        app.add_asset_loader(WeaponDefinitionLoader);
+        app.init_resource::<LoadedWeapons>();
+        app.add_system({
+            load_level_weapons
+                .in_set(OnUpdate(AppState::LoadLevel))
+                .before(LevelPopulationSet)
+        });
+    }
+}
+
+/// The weapon rifle spawns use when their level does not specify one. Weapon files are relative
+/// to the `weapons` directory.
+pub const DEFAULT_WEAPON: &str = "rifle.weapon.ron";
+
+/// The tuning of a rifle. Loaded from `.weapon.ron` files.
+#[derive(TypeUuid, Serialize, Deserialize, Debug, Clone)]
+#[uuid = "0d7b1f64-3a5e-4c1b-8f4e-6c2a9d8e7b31"]
+pub struct WeaponDefinition {
+    pub model: String,
+    /// Half-extents of the rifle's cuboid collider.
+    pub collider_half_extents: Vec3,
+    /// Where the rifle is attached to its holder, in the holder's coordinates.
+    pub grip_anchor: Vec3,
+    /// Where bullets are spawned, in the rifle's coordinates.
+    pub muzzle_offset: Vec3,
+    pub bullet_speed: f32,
+    /// Damage per bullet, when the level's [`DamageRules`](crate::level::DamageRules) use health.
+    #[serde(default = "default_bullet_damage")]
+    pub bullet_damage: f32,
+    /// Only used for how hard bullets push whatever they hit.
+    #[serde(default = "default_bullet_mass")]
+    pub bullet_mass: f32,
+    /// How long a rifle that hit the ground waits before floating back up.
+    pub wait_before_float: f32,
+    /// How long after shooting the rifle cannot be picked up.
+    pub cooldown: f32,
+    /// The velocity the rifle is flung with after shooting, in the rifle's coordinates. The X and
+    /// Z components are the maximum of a random velocity in either direction.
+    pub fling_velocity: Vec3,
+    /// The speed a thrown rifle flies at along its aim.
+    #[serde(default = "default_throw_speed")]
+    pub throw_speed: f32,
+    /// How hard a thrown rifle bumps whoever it hits.
+    #[serde(default = "default_throw_bump_impulse")]
+    pub throw_bump_impulse: f32,
+    /// Without a magazine, the rifle fires a single shot and is flung.
+    #[serde(default)]
+    pub magazine: Option<MagazineDefinition>,
+}
+
+fn default_bullet_damage() -> f32 {
+    40.0
+}
+
+fn default_bullet_mass() -> f32 {
+    0.05
+}
+
+fn default_throw_speed() -> f32 {
+    40.0
+}
+
+fn default_throw_bump_impulse() -> f32 {
+    20.0
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MagazineDefinition {
+    pub rounds: usize,
+    #[serde(default)]
+    pub fling: FlingRule,
+}
+
+/// When a rifle with a magazine gets flung away from its holder.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlingRule {
+    /// The rifle is flung together with the last round.
+    #[default]
+    WithLastShot,
+    /// The rifle stays with its holder after the last round, and pulling the trigger again flings
+    /// it without firing.
+    OnEmptyTrigger,
+}
+
+/// The definition a rifle was spawned with.
+#[derive(Component)]
+pub struct Weapon(pub Handle<WeaponDefinition>);
+
+/// Keeps the weapon definitions used by levels loaded, keyed by their file.
+#[derive(Resource, Default)]
+pub struct LoadedWeapons {
+    handles: HashMap<String, Handle<WeaponDefinition>>,
+}
+
+impl LoadedWeapons {
+    pub fn handle(&self, file: &str) -> Option<&Handle<WeaponDefinition>> {
+        self.handles.get(file)
+    }
+}
+
+fn load_level_weapons(
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+    asset_server: Res<AssetServer>,
+    mut loaded_weapons: ResMut<LoadedWeapons>,
+) {
+    let Some(level) = levels.get(&current_level.handle) else { return };
+    for RifleSpawn { weapon, .. } in level.rifles.iter() {
+        if !loaded_weapons.handles.contains_key(weapon) {
+            let path = format!("weapons/{}", weapon);
+            let handle = asset_server.load(path.as_str());
+            loaded_weapons.handles.insert(weapon.clone(), handle);
+        }
+    }
+
+    // A file that does not exist (e.g. a typo in the editor's Weapon field) would otherwise keep
+    // the level loading forever
+    let failed_weapons = loaded_weapons
+        .handles
+        .iter()
+        .filter(|(weapon, handle)| {
+            weapon.as_str() != DEFAULT_WEAPON
+                && asset_server.get_load_state(*handle) == LoadState::Failed
+        })
+        .map(|(weapon, _)| weapon.clone())
+        .collect::<Vec<_>>();
+    for weapon in failed_weapons {
+        warn!(
+            "Unable to load weapon {:?}, using {:?} instead",
+            weapon, DEFAULT_WEAPON
+        );
+        let path = format!("weapons/{}", DEFAULT_WEAPON);
+        let handle = asset_server.load(path.as_str());
+        loaded_weapons.handles.insert(weapon, handle);
+    }
+}
+
+/// Run condition for populating the level - rifles need their definitions to be spawned. Rifles
+/// whose definition failed to load, even with [`DEFAULT_WEAPON`], are not spawned.
+pub fn level_weapons_loaded(
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+    loaded_weapons: Res<LoadedWeapons>,
+    weapon_definitions: Res<Assets<WeaponDefinition>>,
+    asset_server: Res<AssetServer>,
+) -> bool {
+    let Some(level) = levels.get(&current_level.handle) else { return false };
+    level.rifles.iter().all(|RifleSpawn { weapon, .. }| {
+        loaded_weapons.handle(weapon).map_or(false, |handle| {
+            // `load_level_weapons` already replaced other failed weapons with the default one
+            weapon_definitions.contains(handle)
+                || asset_server.get_load_state(handle) == LoadState::Failed
+        })
+    })
+}
+
+struct WeaponDefinitionLoader;
+
+impl AssetLoader for WeaponDefinitionLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let definition: WeaponDefinition = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(definition));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["weapon.ron"]
+    }
+}